@@ -0,0 +1,134 @@
+use std::{
+    mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::all::{ChannelId, Context as SerenityContext, GuildId, Ready, VoiceState};
+use songbird::Call;
+use tokio::sync::Mutex;
+
+use crate::{commands::DEFAULT_MAX_PLAYLIST_ENTRIES, player, saved_queue, Data};
+
+/// The channel the bot is pinned to with 24/7 mode, if it is on in the guild
+pub fn pinned_channel(data: &Data, guild_id: GuildId) -> Option<ChannelId> {
+    data.settings.get(|settings| {
+        settings
+            .get(&guild_id)
+            .and_then(|settings| settings.always_on_channel)
+    })
+}
+
+/// Join the pinned channel and make sure there is something to play
+pub async fn connect(
+    ctx: &SerenityContext,
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Option<Arc<Mutex<Call>>> {
+    let manager = songbird::get(ctx).await?;
+    match player::connect(&manager, data, guild_id, channel_id).await {
+        Ok(call_lock) => {
+            refill(data, guild_id, &call_lock).await;
+            Some(call_lock)
+        }
+        Err(e) => {
+            tracing::error!(err = %e, "Failed to join pinned channel {}.", channel_id);
+            None
+        }
+    }
+}
+
+/// How long to wait before trying the fallback playlist again after it failed to load
+const FALLBACK_RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// Bring back the saved queue, or else queue the fallback playlist from the config, when nothing
+/// else is queued
+pub async fn refill(data: &Data, guild_id: GuildId, call_lock: &Mutex<Call>) {
+    // Several events can find the queue empty at once, only the first one fills it
+    let busy = data
+        .guild_states
+        .update(guild_id, |state| mem::replace(&mut state.refilling, true));
+    if busy {
+        return;
+    }
+    refill_queue(data, guild_id, call_lock).await;
+    data.guild_states
+        .update(guild_id, |state| state.refilling = false);
+}
+
+async fn refill_queue(data: &Data, guild_id: GuildId, call_lock: &Mutex<Call>) {
+    if !call_lock.lock().await.queue().is_empty() {
+        return;
+    }
+    if saved_queue::restore(data, guild_id, call_lock).await > 0 {
+        return;
+    }
+    let Some(url) = &data.config.fallback_playlist else {
+        return;
+    };
+    let failed_at = data
+        .guild_states
+        .get(guild_id, |state| state.fallback_failed_at);
+    if failed_at.is_some_and(|failed_at| failed_at.elapsed() < FALLBACK_RETRY) {
+        return;
+    }
+
+    let max_tracks = data
+        .config
+        .max_playlist_entries
+        .unwrap_or(DEFAULT_MAX_PLAYLIST_ENTRIES);
+    let resolved = match data.resolvers.resolve(url, max_tracks).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            // Only warn the first time so a broken link doesn't fill up the log
+            if failed_at.is_none() {
+                tracing::warn!(err = %e, "Failed to load the fallback playlist \"{}\".", url);
+            }
+            data.guild_states.update(guild_id, |state| {
+                state.fallback_failed_at = Some(Instant::now());
+            });
+            return;
+        }
+    };
+    data.guild_states
+        .update(guild_id, |state| state.fallback_failed_at = None);
+
+    let volume = f32::from(data.guild_volume(guild_id)) / 100.0;
+    let mut call = call_lock.lock().await;
+    // Something may have been queued while the playlist was loading
+    if !call.queue().is_empty() {
+        return;
+    }
+    for track in resolved.tracks {
+        player::enqueue(&mut call, track.input, track.info, volume).await;
+    }
+}
+
+/// Rejoin the pinned channels, called on every ready event since a new gateway session drops
+/// the voice connections of the bot
+pub async fn on_ready(ctx: &SerenityContext, data: &Data, ready: &Ready) {
+    for guild in &ready.guilds {
+        if let Some(channel_id) = pinned_channel(data, guild.id) {
+            connect(ctx, data, guild.id, channel_id).await;
+        }
+    }
+}
+
+/// Go back to the pinned channel when the bot gets disconnected from it or moved elsewhere
+pub async fn on_voice_state_update(
+    ctx: &SerenityContext,
+    data: &Data,
+    guild_id: GuildId,
+    voice_state: &VoiceState,
+) {
+    if voice_state.user_id != ctx.cache.current_user().id {
+        return;
+    }
+    match pinned_channel(data, guild_id) {
+        Some(channel_id) if voice_state.channel_id != Some(channel_id) => {
+            connect(ctx, data, guild_id, channel_id).await;
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+use serenity::all::{ChannelId, Context as SerenityContext, GuildId};
+
+use crate::{always_on, player, Data};
+
+/// Seconds to stay in a voice channel everyone else left when the config doesn't say otherwise
+const DEFAULT_ALONE_TIMEOUT_SECS: u64 = 60;
+
+/// Minutes to stay with an empty queue when the config doesn't say otherwise
+const DEFAULT_IDLE_TIMEOUT_MINS: u64 = 10;
+
+/// How often guilds are checked for an empty queue
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The voice channel the bot is in, if it is in the guild at all
+pub async fn bot_channel(ctx: &SerenityContext, guild_id: GuildId) -> Option<ChannelId> {
+    let manager = songbird::get(ctx).await?;
+    let call = manager.get(guild_id)?;
+    let channel = call.lock().await.current_channel()?;
+    Some(ChannelId::new(channel.0.get()))
+}
+
+/// Check if nobody except bots is in the voice channel
+fn is_alone(ctx: &SerenityContext, guild_id: GuildId, channel_id: ChannelId) -> bool {
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return false;
+    };
+    !guild
+        .voice_states
+        .values()
+        .filter(|state| state.channel_id == Some(channel_id))
+        .any(|state| {
+            let is_bot = state
+                .member
+                .as_ref()
+                .map(|member| member.user.bot)
+                .or_else(|| ctx.cache.user(state.user_id).map(|user| user.bot));
+            !is_bot.unwrap_or(false)
+        })
+}
+
+/// Leave the voice channel of the guild, then tell the channel the bot was last used in why
+async fn leave(ctx: &SerenityContext, data: &Data, guild_id: GuildId, reason: &str) {
+    let Some(manager) = songbird::get(ctx).await else {
+        return;
+    };
+    // Checked again here since 24/7 mode may have been turned on while counting down
+    if manager.get(guild_id).is_none() || always_on::pinned_channel(data, guild_id).is_some() {
+        return;
+    }
+
+    let text_channel = data.guild_states.update(guild_id, |state| {
+        state.alone_since = None;
+        state.idle_since = None;
+        state.text_channel
+    });
+    if let Err(e) = player::disconnect(&manager, data, guild_id).await {
+        tracing::error!(err = %e, "Failed to leave voice channel in {}.", guild_id);
+    }
+    if let Some(channel) = text_channel {
+        if let Err(e) = channel.say(ctx, reason).await {
+            tracing::warn!(err = %e, "Failed to say why the bot is leaving.");
+        }
+    }
+}
+
+/// Start counting down to leaving when the bot is left alone, called on every voice state update
+pub async fn on_voice_state_update(ctx: &SerenityContext, data: &Data, guild_id: GuildId) {
+    let alone = match bot_channel(ctx, guild_id).await {
+        // Staying around the clock includes staying when nobody is listening
+        Some(_) if always_on::pinned_channel(data, guild_id).is_some() => false,
+        Some(channel) => is_alone(ctx, guild_id, channel),
+        None => false,
+    };
+    if !alone {
+        data.guild_states
+            .update(guild_id, |state| state.alone_since = None);
+        return;
+    }
+    let started = data
+        .guild_states
+        .update(guild_id, |state| match state.alone_since {
+            Some(_) => None,
+            None => Some(*state.alone_since.insert(Instant::now())),
+        });
+    let Some(started) = started else {
+        // Already counting down
+        return;
+    };
+
+    let timeout = Duration::from_secs(
+        data.config
+            .alone_timeout_secs
+            .unwrap_or(DEFAULT_ALONE_TIMEOUT_SECS),
+    );
+    let (ctx, data) = (ctx.clone(), data.clone());
+    tokio::spawn(async move {
+        tokio::time::sleep(timeout).await;
+        // Someone joining in the meantime resets the countdown
+        let still_alone = data
+            .guild_states
+            .get(guild_id, |state| state.alone_since == Some(started));
+        if still_alone {
+            leave(
+                &ctx,
+                &data,
+                guild_id,
+                "Leaving the voice channel since everyone else left.",
+            )
+            .await;
+        }
+    });
+}
+
+/// Periodically leave the voice channels of guilds that haven't had anything to play for a while,
+/// and refill the queue of guilds in 24/7 mode instead
+pub fn spawn_idle_checker(ctx: SerenityContext, data: Data) {
+    let timeout = Duration::from_secs(
+        data.config
+            .idle_timeout_mins
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT_MINS)
+            * 60,
+    );
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(manager) = songbird::get(&ctx).await else {
+                continue;
+            };
+            let calls = manager.iter().collect::<Vec<_>>();
+            for (guild_id, call) in calls {
+                let guild_id = GuildId::new(guild_id.0.get());
+                if always_on::pinned_channel(&data, guild_id).is_some() {
+                    always_on::refill(&data, guild_id, &call).await;
+                    continue;
+                }
+                let idle = call.lock().await.queue().is_empty();
+                let idle_since = data.guild_states.update(guild_id, |state| {
+                    if idle {
+                        Some(*state.idle_since.get_or_insert_with(Instant::now))
+                    } else {
+                        state.idle_since = None;
+                        None
+                    }
+                });
+                if idle_since.is_some_and(|since| since.elapsed() >= timeout) {
+                    let reason = format!(
+                        "Leaving the voice channel since nothing has been played for {} minutes.",
+                        timeout.as_secs() / 60
+                    );
+                    leave(&ctx, &data, guild_id, &reason).await;
+                }
+            }
+        }
+    });
+}
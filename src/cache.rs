@@ -0,0 +1,379 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use parking_lot::Mutex;
+use reqwest::Url;
+use tokio::{process::Command, sync::Semaphore, task::JoinHandle};
+
+/// Most songs downloaded into the cache at the same time
+const MAX_DOWNLOADS: usize = 2;
+
+/// Directory the cache keeps its songs in, inside the configured one so nothing else in there is
+/// ever mistaken for a song and removed
+const SONG_DIR: &str = "youtube";
+
+/// Directory inside the song directory where downloads go until they are finished
+const DOWNLOAD_DIR: &str = ".downloading";
+
+/// Extensions of the audio formats YouTube serves, the only files the cache keeps
+const SONG_EXTENSIONS: &[&str] = &["webm", "m4a", "mp4", "opus", "ogg", "mp3", "aac"];
+
+#[derive(Debug, Clone)]
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub size: u64,
+    pub max_size: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Share of lookups that were found in the cache, from 0 to 1
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// Songs downloaded to disk by their YouTube video id, the least recently played are removed
+/// when the cache grows over its size limit
+#[derive(Debug)]
+pub struct AudioCache {
+    dir: PathBuf,
+    max_size: u64,
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Ids that are being downloaded right now
+    pending: Mutex<HashSet<String>>,
+    downloads: Semaphore,
+    /// Songs being marked as used on blocking threads
+    touches: Mutex<Vec<JoinHandle<()>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Get the id of a YouTube video from any of the urls it can be shared with
+pub fn video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.trim_start_matches("www.");
+    let id = match host {
+        "youtu.be" => url.path_segments()?.next()?.to_owned(),
+        "youtube.com" | "m.youtube.com" | "music.youtube.com" => {
+            let mut segments = url.path_segments()?;
+            match segments.next()? {
+                "watch" => url
+                    .query_pairs()
+                    .find(|(key, _)| key == "v")
+                    .map(|(_, id)| id.into_owned())?,
+                "shorts" | "live" | "embed" => segments.next()?.to_owned(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    // The id ends up in a file name, so only allow what YouTube uses
+    is_video_id(&id).then_some(id)
+}
+
+/// Whether the file looks like one the cache wrote, named after a video id with an audio extension
+fn is_song_file(path: &Path) -> bool {
+    let id = path.file_stem().and_then(|s| s.to_str());
+    let extension = path.extension().and_then(|e| e.to_str());
+    match (id, extension) {
+        (Some(id), Some(extension)) => is_video_id(id) && SONG_EXTENSIONS.contains(&extension),
+        _ => false,
+    }
+}
+
+fn is_video_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl AudioCache {
+    /// Open the cache in the directory, picking up the songs downloaded before
+    pub fn open(dir: impl AsRef<Path>, max_size: u64) -> io::Result<AudioCache> {
+        let dir = dir.as_ref().join(SONG_DIR);
+        let download_dir = dir.join(DOWNLOAD_DIR);
+        if download_dir.exists() {
+            // Left over from downloads that were cut off by a restart
+            fs::remove_dir_all(&download_dir)?;
+        }
+        fs::create_dir_all(&download_dir)?;
+
+        let mut entries = HashMap::new();
+        for file in fs::read_dir(&dir)? {
+            let file = file?;
+            let metadata = file.metadata()?;
+            let path = file.path();
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !metadata.is_file() || !is_song_file(&path) {
+                continue;
+            }
+            let entry = Entry {
+                size: metadata.len(),
+                last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                path: path.clone(),
+            };
+            entries.insert(id.to_owned(), entry);
+        }
+
+        let cache = AudioCache {
+            dir,
+            max_size,
+            entries: Mutex::new(entries),
+            pending: Mutex::new(HashSet::new()),
+            downloads: Semaphore::new(MAX_DOWNLOADS),
+            touches: Mutex::new(Vec::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        };
+        let evicted = cache.evict(&mut cache.entries.lock());
+        remove_files(&evicted);
+        Ok(cache)
+    }
+
+    /// Get the file of a cached song, marking it as recently used
+    pub fn get(&self, id: &str) -> Option<PathBuf> {
+        let (path, last_used) = {
+            let mut entries = self.entries.lock();
+            let Some(entry) = entries.get_mut(id) else {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            };
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            entry.last_used = SystemTime::now();
+            (entry.path.clone(), entry.last_used)
+        };
+
+        // The modification time is how the order survives restarts
+        let touched_path = path.clone();
+        let touch = tokio::task::spawn_blocking(move || {
+            let touched = fs::File::options()
+                .write(true)
+                .open(&touched_path)
+                .and_then(|file| file.set_modified(last_used));
+            if let Err(e) = touched {
+                tracing::warn!(err = %e, "Failed to mark \"{}\" as used.", touched_path.display());
+            }
+        });
+        let mut touches = self.touches.lock();
+        touches.retain(|touch| !touch.is_finished());
+        touches.push(touch);
+        Some(path)
+    }
+
+    /// Wait until the songs looked up so far are marked as used on disk
+    #[cfg(test)]
+    async fn flush(&self) {
+        let touches = std::mem::take(&mut *self.touches.lock());
+        for touch in touches {
+            touch.await.expect("Marking a song as used panicked.");
+        }
+    }
+
+    /// Move a downloaded file into the cache, making room for it if needed. Works with the files
+    /// directly, so it belongs on a blocking thread.
+    pub fn insert(&self, id: &str, file: &Path) -> io::Result<()> {
+        let mut name = id.to_owned();
+        if let Some(extension) = file.extension().and_then(|e| e.to_str()) {
+            name = format!("{}.{}", name, extension);
+        }
+        let path = self.dir.join(name);
+        if !is_song_file(&path) {
+            let _ = fs::remove_file(file);
+            return Err(io::Error::other(format!(
+                "\"{}\" isn't an audio file the cache keeps",
+                file.display()
+            )));
+        }
+        fs::rename(file, &path)?;
+        let entry = Entry {
+            size: fs::metadata(&path)?.len(),
+            last_used: SystemTime::now(),
+            path,
+        };
+
+        // Only the map is changed while locked, the files are removed once others can use it again
+        let removed = {
+            let mut entries = self.entries.lock();
+            let old = entries
+                .insert(id.to_owned(), entry)
+                .filter(|old| old.path != entries[id].path);
+            let mut removed = self.evict(&mut entries);
+            removed.extend(old.map(|old| old.path));
+            removed
+        };
+        remove_files(&removed);
+        Ok(())
+    }
+
+    /// Take the least recently used songs out until the cache fits in its size limit, returning
+    /// the files to remove
+    fn evict(&self, entries: &mut HashMap<String, Entry>) -> Vec<PathBuf> {
+        let mut size: u64 = entries.values().map(|e| e.size).sum();
+        let mut evicted = Vec::new();
+        while size > self.max_size {
+            let Some(id) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+            let entry = entries.remove(&id).expect("Id was just found in the map.");
+            size -= entry.size;
+            evicted.push(entry.path);
+        }
+        evicted
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock();
+        CacheStats {
+            entries: entries.len(),
+            size: entries.values().map(|e| e.size).sum(),
+            max_size: self.max_size,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Download the song into the cache in the background, unless that is already happening
+    pub fn download(self: &Arc<Self>, id: String, url: String) {
+        if self.entries.lock().contains_key(&id) || !self.pending.lock().insert(id.clone()) {
+            return;
+        }
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let result = match cache.downloads.acquire().await {
+                Ok(_permit) => cache.run_download(&id, &url).await,
+                Err(e) => Err(io::Error::other(e)),
+            };
+            match result {
+                Ok(()) => tracing::debug!("Cached \"{}\".", url),
+                Err(e) => tracing::warn!(err = %e, "Failed to cache \"{}\".", url),
+            }
+            cache.pending.lock().remove(&id);
+        });
+    }
+
+    async fn run_download(self: &Arc<Self>, id: &str, url: &str) -> io::Result<()> {
+        let template = self.dir.join(DOWNLOAD_DIR).join(format!("{}.%(ext)s", id));
+        let output = Command::new("yt-dlp")
+            .args(["-f", "bestaudio", "--no-playlist", "--no-simulate"])
+            .args(["--print", "after_move:filepath", "-o"])
+            .arg(template)
+            .arg(url)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(stderr.trim().to_owned()));
+        }
+        let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        let (cache, id) = (self.clone(), id.to_owned());
+        tokio::task::spawn_blocking(move || cache.insert(&id, &path))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(e) = fs::remove_file(path) {
+            tracing::warn!(err = %e, "Failed to remove \"{}\" from the cache.", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_video_id() {
+        let id = Some("dQw4w9WgXcQ".to_owned());
+        assert_eq!(
+            video_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42"),
+            id
+        );
+        assert_eq!(video_id("https://youtu.be/dQw4w9WgXcQ?si=abc"), id);
+        assert_eq!(
+            video_id("https://music.youtube.com/watch?v=dQw4w9WgXcQ"),
+            id
+        );
+        assert_eq!(video_id("https://youtube.com/shorts/dQw4w9WgXcQ"), id);
+        assert_eq!(
+            video_id("https://www.youtube.com/playlist?list=PL123"),
+            None
+        );
+        assert_eq!(video_id("https://youtu.be/abc%2F..%2Fetc"), None);
+        assert_eq!(video_id("https://example.com/watch?v=dQw4w9WgXcQ"), None);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let base = std::env::temp_dir().join(format!("music_bot_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        // Other files in the configured directory are left alone
+        fs::create_dir_all(&base).unwrap();
+        fs::write(base.join("settings.json"), [0; 100]).unwrap();
+
+        let cache = AudioCache::open(&base, 25).unwrap();
+        let dir = base.join(SONG_DIR);
+        let add = |id: &str| {
+            let file = dir.join(DOWNLOAD_DIR).join(format!("{}.webm", id));
+            fs::write(&file, [0; 10]).unwrap();
+            cache.insert(id, &file).unwrap();
+            // Make sure every song gets its own time
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        add("a");
+        add("b");
+        assert!(cache.get("a").is_some());
+        add("c");
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("c"), Some(dir.join("c.webm")));
+        assert!(!dir.join("b.webm").exists());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.size), (2, 20));
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+
+        // The order is kept when opening the cache again, once the files are marked as used
+        cache.flush().await;
+        drop(cache);
+        fs::write(dir.join("notes.txt"), [0; 100]).unwrap();
+        let cache = AudioCache::open(&base, 15).unwrap();
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("c").is_some());
+        assert!(base.join("settings.json").exists());
+        assert!(dir.join("notes.txt").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
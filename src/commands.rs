@@ -1,13 +1,38 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use poise::{ChoiceParameter, CreateReply, ReplyHandle};
+use serenity::all::{
+    Attachment, AutocompleteChoice, ChannelId, Colour, ComponentInteractionCollector,
+    ComponentInteractionDataKind, CreateActionRow, CreateButton, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, GuildId, Message, User,
+};
 use serenity::futures::future::join_all;
-use songbird::{input::YoutubeDl, TrackEvent};
+use songbird::{
+    input::{File, HttpRequest, YoutubeDl},
+    tracks::{ControlError, PlayError as TrackPlayError, PlayMode, Queued, TrackHandle},
+    Call, Event, TrackEvent,
+};
 
+use tokio::sync::Mutex;
 use tracing::instrument;
 
 use crate::{
-    events::TrackErrorNotifier,
-    get_songbird_manager,
-    typekeys::{HttpKey, SongTitleKey, SongUrlKey},
-    Context, Error,
+    always_on,
+    events::PodcastProgressSaver,
+    get_http_client, get_songbird_manager,
+    guild_state::LoopMode,
+    history::{self, HistoryEntry, MAX_HISTORY},
+    library::Library,
+    player::{self, TrackInfo},
+    podcast::{self, Progress},
+    queue_edit,
+    resolver::{self, PlayError, ResolvedTrack},
+    saved_queue,
+    timestamp::{format_duration, parse_seek, progress_bar},
+    trimmed_embed::{truncate_string_to_char_boundary, TrimmedEmbed},
+    typekeys::SongDurationKey,
+    Context, Error, MAX_VOLUME,
 };
 
 /// Show this help menu
@@ -36,188 +61,1790 @@ pub async fn help(
 #[poise::command(prefix_command, slash_command)]
 pub async fn play(
     ctx: Context<'_>,
-    #[description = "What to play"] url: String,
+    #[description = "What to play"]
+    #[autocomplete = "autocomplete_song"]
+    url: String,
 ) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild().map(|g| g.id) else {
         ctx.say("This command is only supported in guilds.").await?;
         return Ok(());
     };
 
-    // Some prepwork before gathering the data
-    let do_search = !url.starts_with("http");
-    let http_client = {
-        let data = ctx.serenity_context().data.read().await;
-        data.get::<HttpKey>()
-            .cloned()
-            .expect("Guaranteed to exist in the typemap.")
+    // Looking up playlists and albums takes a while
+    ctx.defer().await?;
+    let max_tracks = ctx
+        .data()
+        .config
+        .max_playlist_entries
+        .unwrap_or(DEFAULT_MAX_PLAYLIST_ENTRIES);
+    let resolved = match ctx.data().resolvers.resolve(&url, max_tracks).await {
+        Ok(resolved) => resolved,
+        Err(e) => return report_play_error(ctx, &url, e).await,
+    };
+    let mut tracks = resolved.tracks;
+    for track in &mut tracks {
+        track.info.requester = Some(ctx.author().id);
+    }
+    let (count, first) = (tracks.len(), tracks[0].info.clone());
+    if !url.contains("://") {
+        let entry = HistoryEntry {
+            title: first.title.clone(),
+            url: first.url.clone(),
+        };
+        remember_searches(ctx, guild_id, vec![entry]);
+    }
+
+    // Add the songs to the queue
+    match &resolved.name {
+        Some(name) if count > 1 => remember_played(ctx, guild_id, name, &url),
+        _ => remember_played(ctx, guild_id, &first.title, &first.url),
+    }
+    if add_to_queue(ctx, guild_id, tracks).await?.is_none() {
+        return Ok(());
+    }
+
+    match resolved.name {
+        Some(name) if count > 1 => {
+            ctx.say(format!("Added {} tracks from \"{}\".", count, name))
+                .await?
+        }
+        _ => {
+            ctx.say(format!("\"{}\" added to queue.", first.title))
+                .await?
+        }
     };
 
-    // Fetch data about the selected video
-    let mut src = if do_search {
-        YoutubeDl::new_search(http_client, url.clone())
+    Ok(())
+}
+
+/// Play an audio file uploaded to Discord
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn play_file(
+    ctx: Context<'_>,
+    #[description = "Audio file to play"] file: Attachment,
+) -> Result<(), Error> {
+    play_attachments(ctx, &[file]).await
+}
+
+/// Play every audio file attached to a message
+#[instrument(skip(msg))]
+#[poise::command(context_menu_command = "Play attachments")]
+pub async fn play_message_attachments(ctx: Context<'_>, msg: Message) -> Result<(), Error> {
+    play_attachments(ctx, &msg.attachments).await
+}
+
+/// Largest attachment that will be played, bigger files take too long to download
+const MAX_ATTACHMENT_SIZE: u32 = 100 * 1024 * 1024;
+
+/// Why an attachment can't be played, if there is anything wrong with it
+fn attachment_problem(attachment: &Attachment) -> Option<String> {
+    let is_audio = attachment
+        .content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type.starts_with("audio/"));
+    if !is_audio {
+        Some(format!("\"{}\" isn't an audio file.", attachment.filename))
+    } else if attachment.size > MAX_ATTACHMENT_SIZE {
+        Some(format!(
+            "\"{}\" is too big, files can be at most {} MB.",
+            attachment.filename,
+            MAX_ATTACHMENT_SIZE / 1024 / 1024
+        ))
     } else {
-        YoutubeDl::new(http_client, url.clone())
+        None
+    }
+}
+
+/// Add the audio attachments to the queue, telling the user about the ones that can't be played
+async fn play_attachments(ctx: Context<'_>, attachments: &[Attachment]) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
     };
-    let mut aux_multiple = src
-        .search(Some(1))
-        .await
-        .expect("Failed to get info about song.");
-    if aux_multiple.len() == 0 {}
-    let aux = aux_multiple.swap_remove(0);
-    let title = aux.title.unwrap_or_else(|| "Unknown".to_owned());
+    if attachments.is_empty() {
+        ctx.say("There are no files to play.").await?;
+        return Ok(());
+    }
 
-    // Add the song to the queue
-    {
-        let songbird = get_songbird_manager(ctx).await;
-        let Some(driver_lock) = songbird.get(guild_id) else {
-            ctx.say("Not in voice channel, can't play.").await?;
-            return Ok(());
-        };
-        let mut driver = driver_lock.lock().await;
-        let handle = driver.enqueue(src.into()).await;
-        let mut typemap = handle.typemap().write().await;
-        typemap.insert::<SongTitleKey>(title.clone());
-        typemap.insert::<SongUrlKey>(url);
+    // Reading the tags means downloading the start of every file
+    ctx.defer().await?;
+    let mut tracks = Vec::new();
+    let mut problems = Vec::new();
+    for attachment in attachments {
+        if let Some(problem) = attachment_problem(attachment) {
+            problems.push(problem);
+            continue;
+        }
+        match ctx.data().resolvers.resolve(&attachment.url, 1).await {
+            Ok(resolved) => tracks.extend(resolved.tracks),
+            Err(e) => {
+                if let PlayError::ExtractorFailed(msg) = &e {
+                    tracing::warn!("Failed to read \"{}\": {}", attachment.url, msg);
+                }
+                problems.push(format!("\"{}\": {}", attachment.filename, e));
+            }
+        }
+    }
+    for track in &mut tracks {
+        track.info.requester = Some(ctx.author().id);
     }
 
-    ctx.say(format!("\"{}\" added to queue.", title)).await?;
+    // Attachment links expire, so they are kept out of the history
+    let mut reply = match tracks.as_slice() {
+        [] => String::new(),
+        [track] => format!("\"{}\" added to queue.", track.info.title),
+        tracks => format!("Added {} files to the queue.", tracks.len()),
+    };
+    if !tracks.is_empty() && add_to_queue(ctx, guild_id, tracks).await?.is_none() {
+        return Ok(());
+    }
+    for problem in problems {
+        reply.push('\n');
+        reply.push_str(&problem);
+    }
+    ctx.say(reply.trim()).await?;
 
     Ok(())
 }
 
-/// Join a voice channel
+/// Tune in to one of the radio stations set up in the config
 #[instrument]
-#[poise::command(prefix_command, aliases("votes"), slash_command)]
-pub async fn join(
+#[poise::command(prefix_command, slash_command)]
+pub async fn radio(
     ctx: Context<'_>,
-    // #[description = "Choice to retrieve votes for"] voice_channel: Option<VoiceState>,
+    #[description = "Name of the station"]
+    #[autocomplete = "autocomplete_station"]
+    #[rest]
+    name: String,
 ) -> Result<(), Error> {
-    let (guild_id, channel_id) = {
-        let Some(guild) = ctx.guild() else {
-            ctx.say("This command is only supported in guilds.").await?;
-            return Ok(());
-        };
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+    let stations = &ctx.data().config.radio_stations;
+    let Some((name, url)) = stations
+        .iter()
+        .find(|(station, _)| station.eq_ignore_ascii_case(name.trim()))
+    else {
+        ctx.say(format!("There is no radio station called \"{}\".", name))
+            .await?;
+        return Ok(());
+    };
 
-        let channel_id = guild
-            .voice_states
-            .get(&ctx.author().id)
-            .and_then(|voice_state| voice_state.channel_id);
-        (guild.id, channel_id)
+    ctx.defer().await?;
+    let resolved = match ctx.data().resolvers.resolve(url, 1).await {
+        Ok(resolved) => resolved,
+        Err(e) => return report_play_error(ctx, url, e).await,
+    };
+    let mut tracks = resolved.tracks;
+    for track in &mut tracks {
+        track.info.requester = Some(ctx.author().id);
+    }
+    remember_played(ctx, guild_id, name, url);
+    if add_to_queue(ctx, guild_id, tracks).await?.is_none() {
+        return Ok(());
+    }
+
+    ctx.say(format!("Tuned in to \"{}\".", name)).await?;
+
+    Ok(())
+}
+
+/// Suggest the radio stations from the config
+async fn autocomplete_station(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+    let mut names = ctx
+        .data()
+        .config
+        .radio_stations
+        .keys()
+        .filter(|name| name.to_lowercase().contains(&partial))
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+        .into_iter()
+        .take(25)
+        .map(|name| AutocompleteChoice::new(name.clone(), name.clone()))
+        .collect()
+}
+
+/// Tell the user why their song couldn't be played
+async fn report_play_error(ctx: Context<'_>, query: &str, e: PlayError) -> Result<(), Error> {
+    if let PlayError::ExtractorFailed(msg) = &e {
+        tracing::warn!("Failed to get info about \"{}\": {}", query, msg);
+    }
+    ctx.say(e.to_string()).await?;
+    Ok(())
+}
+
+/// Add tracks to the queue of the guild, returning None if the bot isn't in a voice channel
+async fn add_to_queue(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+    tracks: Vec<ResolvedTrack>,
+) -> Result<Option<Vec<TrackHandle>>, Error> {
+    let Some(driver_lock) = voice_call(ctx, guild_id).await? else {
+        return Ok(None);
     };
+    let volume = f32::from(ctx.data().guild_volume(guild_id)) / 100.0;
+    let mut driver = driver_lock.lock().await;
+    let mut handles = Vec::with_capacity(tracks.len());
+    for track in tracks {
+        handles.push(player::enqueue(&mut driver, track.input, track.info, volume).await);
+    }
+    drop(driver);
+    save_queue(ctx, guild_id, &driver_lock).await;
+    Ok(Some(handles))
+}
 
-    let connect_to = match channel_id {
-        Some(channel) => channel,
-        None => {
-            ctx.say("Not in a voice channel").await?;
-            return Ok(());
+/// Write the queue of the guild to disk after a command changed it
+async fn save_queue(ctx: Context<'_>, guild_id: GuildId, call_lock: &Mutex<Call>) {
+    let data = ctx.data();
+    saved_queue::save(&data.saved_queues, &data.guild_states, guild_id, call_lock).await;
+}
+
+/// Add a song to the play history of the guild so it can be suggested later
+fn remember_played(ctx: Context<'_>, guild_id: GuildId, title: &str, url: &str) {
+    ctx.data().history.update_later(|history| {
+        let entry = HistoryEntry {
+            title: title.to_owned(),
+            url: url.to_owned(),
+        };
+        history::record(history.entry(guild_id).or_default(), entry, MAX_HISTORY);
+    });
+}
+
+/// How many search results are kept around for suggestions
+const MAX_RECENT_SEARCHES: usize = 25;
+
+/// Keep search results around so they can be suggested when using play, best result first
+fn remember_searches(ctx: Context<'_>, guild_id: GuildId, results: Vec<HistoryEntry>) {
+    ctx.data().guild_states.update(guild_id, |state| {
+        for entry in results.into_iter().rev() {
+            history::record(&mut state.recent_searches, entry, MAX_RECENT_SEARCHES);
         }
+    });
+}
+
+/// Suggest songs from recent searches and songs played before in the guild
+async fn autocomplete_song(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![];
     };
+    let searches = ctx
+        .data()
+        .guild_states
+        .get(guild_id, |s| s.recent_searches.clone());
+    let played = ctx
+        .data()
+        .history
+        .get(|history| history.get(&guild_id).cloned().unwrap_or_default());
 
-    let manager = get_songbird_manager(ctx).await;
-    match manager.join(guild_id, connect_to).await {
-        Ok(handler_lock) => {
-            // Attach an event handler to see notifications of all track errors.
-            let mut handler = handler_lock.lock().await;
-            handler.add_global_event(TrackEvent::Error.into(), TrackErrorNotifier);
-        }
-        Err(e) => {
-            println!("Faield to join channel: {:?}", e);
-            ctx.say("Failed to join channel.").await?;
-            return Err(Box::new(e));
-        }
+    // Discord doesn't allow choices with values longer than 100 characters
+    let entries = searches
+        .iter()
+        .chain(played.iter())
+        .filter(|e| e.url.len() <= 100);
+    history::suggestions(entries, partial, 25)
+        .into_iter()
+        .map(|e| {
+            let mut name = e.title.clone();
+            truncate_string_to_char_boundary(&mut name, 100);
+            AutocompleteChoice::new(name, e.url.clone())
+        })
+        .collect()
+}
+
+/// Most entries added from a playlist when the config doesn't say otherwise
+pub const DEFAULT_MAX_PLAYLIST_ENTRIES: usize = 100;
+
+/// Browse and play the music stored where the bot is running
+#[instrument]
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("library_search", "library_play", "library_rescan"),
+    subcommand_required
+)]
+pub async fn library(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Get the music library, telling the user if there isn't one
+async fn get_library(ctx: Context<'_>) -> Result<Option<Arc<Library>>, Error> {
+    let library = ctx.data().library.clone();
+    if library.is_none() {
+        ctx.say("There is no music library set up on this bot.")
+            .await?;
     }
+    Ok(library)
+}
+
+/// Number of songs shown when searching the library
+const LIBRARY_RESULTS: usize = 15;
+
+/// Search the library by title, artist, album or file name
+#[instrument]
+#[poise::command(prefix_command, slash_command, rename = "search")]
+pub async fn library_search(
+    ctx: Context<'_>,
+    #[description = "What to search for"]
+    #[rest]
+    query: String,
+) -> Result<(), Error> {
+    let Some(library) = get_library(ctx).await? else {
+        return Ok(());
+    };
+    let results = library.search(&query, LIBRARY_RESULTS);
+    if results.is_empty() {
+        ctx.say(format!("Nothing in the library matches \"{}\".", query))
+            .await?;
+        return Ok(());
+    }
+
+    let lines = results
+        .iter()
+        .map(|track| {
+            let album = track
+                .tags
+                .album
+                .as_ref()
+                .map(|album| format!(" ({})", album))
+                .unwrap_or_default();
+            let duration = track
+                .duration()
+                .map(format_duration)
+                .unwrap_or_else(|| "?:??".to_owned());
+            format!("`{}` {}{} `{}`", track.id, track.title(), album, duration)
+        })
+        .collect::<Vec<_>>();
+    let embed = TrimmedEmbed::new()
+        .title(format!("Library results for \"{}\"", query))
+        .description(lines.join("\n"))
+        .colour(Colour::BLURPLE)
+        .footer("Play one with /library play <id>");
+    ctx.send(CreateReply::default().embed(embed.into())).await?;
 
-    ctx.say("Ready to play").await?;
     Ok(())
 }
 
-/// Leave the current voice channel
+/// Suggest songs from the library, matching the same way as searching it
+async fn autocomplete_library(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let Some(library) = &ctx.data().library else {
+        return vec![];
+    };
+    library
+        .search(partial, 25)
+        .into_iter()
+        .map(|track| {
+            let mut name = format!("{}. {}", track.id, track.title());
+            truncate_string_to_char_boundary(&mut name, 100);
+            AutocompleteChoice::new(name, track.id)
+        })
+        .collect()
+}
+
+/// Play a song from the library
 #[instrument]
-#[poise::command(prefix_command, slash_command)]
-pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
+#[poise::command(prefix_command, slash_command, rename = "play")]
+pub async fn library_play(
+    ctx: Context<'_>,
+    #[description = "Number of the song, from searching the library"]
+    #[autocomplete = "autocomplete_library"]
+    id: usize,
+) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild().map(|g| g.id) else {
         ctx.say("This command is only supported in guilds.").await?;
         return Ok(());
     };
+    let Some(library) = get_library(ctx).await? else {
+        return Ok(());
+    };
+    let Some(track) = library.get(id) else {
+        ctx.say(format!("There is no song number {} in the library.", id))
+            .await?;
+        return Ok(());
+    };
 
-    let manager = get_songbird_manager(ctx).await;
-    let has_handler = manager.get(guild_id).is_some();
+    let mut info = track.info();
+    info.requester = Some(ctx.author().id);
+    let title = info.title.clone();
+    remember_played(ctx, guild_id, &title, &info.url);
+    let input = File::new(track.path).into();
+    // Joining the voice channel may take a while
+    ctx.defer().await?;
+    if add_to_queue(ctx, guild_id, vec![ResolvedTrack { info, input }])
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
 
-    if has_handler {
-        if let Err(e) = manager.remove(guild_id).await {
-            ctx.say(format!("Failed: {:?}", e)).await?;
+    ctx.say(format!("\"{}\" added to queue.", title)).await?;
+
+    Ok(())
+}
+
+/// Look through the library directory again for new and changed songs
+#[instrument]
+#[poise::command(prefix_command, slash_command, rename = "rescan", owners_only)]
+pub async fn library_rescan(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(library) = get_library(ctx).await? else {
+        return Ok(());
+    };
+
+    ctx.defer().await?;
+    match library.rescan().await {
+        Ok(count) => {
+            ctx.say(format!("Found {} songs in the library.", count))
+                .await?
+        }
+        Err(e) => {
+            tracing::error!(err = %e, "Failed to scan the library.");
+            ctx.say("Failed to read the library directory.").await?
         }
+    };
 
-        ctx.say("Left voice channel").await?;
-    } else {
-        ctx.say("Not in a voice channel").await?;
-    }
+    Ok(())
+}
 
+/// Replace a reply with a select menu by what came of choosing from it
+async fn close_menu(
+    ctx: Context<'_>,
+    reply: &ReplyHandle<'_>,
+    content: impl Into<String>,
+) -> Result<(), Error> {
+    reply
+        .edit(
+            ctx,
+            CreateReply::default().content(content).components(vec![]),
+        )
+        .await?;
     Ok(())
 }
 
-/// Show the current queue
+/// Send a select menu and wait for the author to choose one of the options, returning the reply to
+/// close the menu with and the index of the chosen option. The menu is closed right away when
+/// nothing is chosen in time, `what` names the things to choose from in that message.
+async fn choose_from_menu<'a>(
+    ctx: Context<'a>,
+    content: String,
+    options: Vec<CreateSelectMenuOption>,
+    placeholder: &str,
+    what: &str,
+) -> Result<(ReplyHandle<'a>, Option<usize>), Error> {
+    let option_count = options.len();
+    let menu_id = format!("{}menu", ctx.id());
+    let menu = CreateSelectMenu::new(&menu_id, CreateSelectMenuKind::String { options })
+        .placeholder(placeholder);
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(content)
+                .components(vec![CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+
+    let press = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id == menu_id)
+        .timeout(Duration::from_secs(30))
+        .await;
+    let Some(press) = press else {
+        close_menu(ctx, &reply, format!("No {} was chosen in time.", what)).await?;
+        return Ok((reply, None));
+    };
+    // Answer right away since joining the voice channel may take longer than Discord waits
+    press.defer(ctx.serenity_context()).await?;
+    let chosen = match &press.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => values
+            .first()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|&i| i < option_count),
+        _ => None,
+    };
+    if chosen.is_none() {
+        tracing::warn!(?press.data, "Got an unexpected answer to the {} menu.", what);
+        close_menu(
+            ctx,
+            &reply,
+            format!("Couldn't tell which {} was chosen.", what),
+        )
+        .await?;
+    }
+    Ok((reply, chosen))
+}
+
+/// Number of results the search command lets you choose from
+const SEARCH_RESULTS: usize = 5;
+
+/// Search YouTube and choose which of the results to play
 #[instrument]
 #[poise::command(prefix_command, slash_command)]
-pub async fn queue(ctx: Context<'_>) -> Result<(), Error> {
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "What to search for"] query: String,
+) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild().map(|g| g.id) else {
         ctx.say("This command is only supported in guilds.").await?;
         return Ok(());
     };
 
-    let songbird = get_songbird_manager(ctx).await;
-    let Some(driver_lock) = songbird.get(guild_id) else {
-        ctx.say("Not in a voice channel, no queue to show.").await?;
+    // Joining the voice channel and searching for multiple songs takes a while
+    ctx.defer().await?;
+    if voice_call(ctx, guild_id).await?.is_none() {
         return Ok(());
+    }
+    let http_client = get_http_client(ctx).await;
+    let mut src = YoutubeDl::new_search(http_client, query.clone());
+    let results = match resolver::search(&mut src, &query, SEARCH_RESULTS).await {
+        Ok(results) => results,
+        Err(e) => return report_play_error(ctx, &query, e).await,
     };
-    let driver = driver_lock.lock().await;
-    if driver.queue().is_empty() {
-        ctx.say("Queue is empty.").await?;
+    let entries = results
+        .iter()
+        .filter_map(|aux| {
+            Some(HistoryEntry {
+                title: aux.title.clone()?,
+                url: aux.source_url.clone()?,
+            })
+        })
+        .collect();
+    remember_searches(ctx, guild_id, entries);
+
+    let options = results
+        .iter()
+        .enumerate()
+        .map(|(i, aux)| {
+            let mut label = format!("{}. {}", i + 1, aux.title.as_deref().unwrap_or("Unknown"));
+            truncate_string_to_char_boundary(&mut label, 100);
+            let mut description = format!(
+                "{} - {}",
+                aux.channel.as_deref().unwrap_or("Unknown channel"),
+                aux.duration
+                    .map(format_duration)
+                    .unwrap_or_else(|| "?:??".to_owned())
+            );
+            truncate_string_to_char_boundary(&mut description, 100);
+            CreateSelectMenuOption::new(label, i.to_string()).description(description)
+        })
+        .collect();
+    let content = format!("Results for \"{}\":", query);
+    let (reply, chosen) =
+        choose_from_menu(ctx, content, options, "Choose a song to play", "song").await?;
+    let Some(aux) = chosen.map(|i| results[i].clone()) else {
         return Ok(());
-    }
-    let current_uuid = driver.queue().current().map(|h| h.uuid());
-    let queue = driver.queue().current_queue();
-    let lines = queue.into_iter().enumerate().map(|(i, handle)| async move {
-        let typemap = handle.typemap().read().await;
-        let name = typemap
-            .get::<SongTitleKey>()
-            .map(|s| s.as_str())
-            .unwrap_or("Unknown")
-            .to_owned();
-        let url = typemap
-            .get::<SongUrlKey>()
-            .map(|s| s.as_str())
-            .unwrap_or("Unknown")
-            .to_owned();
-        if Some(handle.uuid()) == current_uuid {
-            format!("{}. {} - {} (currently playing)", i + 1, name, url)
-        } else {
-            format!("{}. {} - {}", i + 1, name, url)
+    };
+    let Some(url) = aux.source_url else {
+        return close_menu(ctx, &reply, "Can't play that song, it doesn't have a link.").await;
+    };
+
+    let title = aux.title.unwrap_or_else(|| "Unknown".to_owned());
+    let info = TrackInfo {
+        title: title.clone(),
+        url: url.clone(),
+        duration: aux.duration,
+        requester: Some(ctx.author().id),
+        live: None,
+    };
+    // Goes through the resolvers so the song can come from the cache
+    let Some(input) = ctx.data().resolvers.replay(&info) else {
+        return close_menu(ctx, &reply, "Don't know how to play that song.").await;
+    };
+    remember_played(ctx, guild_id, &title, &url);
+    match add_to_queue(ctx, guild_id, vec![ResolvedTrack { info, input }]).await {
+        Ok(Some(_)) => close_menu(ctx, &reply, format!("\"{}\" added to queue.", title)).await,
+        Ok(None) => close_menu(ctx, &reply, "Nothing was added to the queue.").await,
+        Err(e) => {
+            close_menu(ctx, &reply, "Failed to add the song to the queue.").await?;
+            Err(e)
         }
-    });
-    let output = join_all(lines).await.join("\n");
-    ctx.say(format!("## Queue:\n```\n{}\n```", output)).await?;
+    }
+}
 
+/// Listen to podcasts from their RSS or Atom feeds
+#[instrument]
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("podcast_feed", "podcast_resume"),
+    subcommand_required
+)]
+pub async fn podcast(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Skip over the current song
+/// Number of recent episodes to choose from, the most a select menu can hold
+const PODCAST_EPISODES: usize = 25;
+
+/// How often the position in a podcast episode is saved while it plays
+const PODCAST_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Choose a recent episode of a podcast to play
 #[instrument]
-#[poise::command(prefix_command, slash_command)]
-pub async fn skip(ctx: Context<'_>) -> Result<(), Error> {
+#[poise::command(prefix_command, slash_command, rename = "feed")]
+pub async fn podcast_feed(
+    ctx: Context<'_>,
+    #[description = "Link to the RSS or Atom feed of the podcast"] feed_url: String,
+) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild().map(|g| g.id) else {
         ctx.say("This command is only supported in guilds.").await?;
         return Ok(());
     };
 
-    let songbird = get_songbird_manager(ctx).await;
-    let Some(driver_lock) = songbird.get(guild_id) else {
-        ctx.say("No playing anything, can't skip.").await?;
+    ctx.defer().await?;
+    let http_client = get_http_client(ctx).await;
+    let feed = match podcast::fetch_feed(&http_client, &feed_url).await {
+        Ok(feed) => feed,
+        Err(e) => {
+            tracing::warn!(err = %e, "Failed to read podcast feed \"{}\".", feed_url);
+            ctx.say(format!("Couldn't read the podcast, {}.", e))
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut episodes = feed.episodes;
+    episodes.truncate(PODCAST_EPISODES);
+    if episodes.is_empty() {
+        ctx.say("The podcast doesn't have any episodes to play.")
+            .await?;
+        return Ok(());
+    }
+
+    let options = episodes
+        .iter()
+        .enumerate()
+        .map(|(i, episode)| {
+            let mut label = episode.title.clone();
+            if label.is_empty() {
+                label = format!("Episode {}", i + 1);
+            }
+            truncate_string_to_char_boundary(&mut label, 100);
+            let mut option = CreateSelectMenuOption::new(label, i.to_string());
+            let details = [
+                episode.published.clone(),
+                episode.duration.map(format_duration),
+            ];
+            let mut description = details
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" - ");
+            if !description.is_empty() {
+                truncate_string_to_char_boundary(&mut description, 100);
+                option = option.description(description);
+            }
+            option
+        })
+        .collect();
+    let content = format!(
+        "Recent episodes of \"{}\":",
+        feed.title.as_deref().unwrap_or(&feed_url)
+    );
+    let (reply, chosen) = choose_from_menu(
+        ctx,
+        content,
+        options,
+        "Choose an episode to play",
+        "episode",
+    )
+    .await?;
+    let Some(episode) = chosen.map(|i| episodes[i].clone()) else {
         return Ok(());
     };
-    let driver = driver_lock.lock().await;
-    driver.queue().skip()?;
-    ctx.say("Skipping to the next song.").await?;
+
+    // Start where the user left off if they have listened to some of it before
+    let progress = ctx.data().podcasts.get(|podcasts| {
+        podcasts
+            .get(&ctx.author().id)
+            .and_then(|listener| listener.episodes.get(&episode.url))
+            .cloned()
+    });
+    let progress = progress.unwrap_or(Progress {
+        title: episode.title,
+        position: Duration::ZERO,
+        duration: episode.duration,
+    });
+    match play_episode(ctx, guild_id, &episode.url, &progress).await {
+        Ok(true) => close_menu(ctx, &reply, episode_added(&progress)).await,
+        Ok(false) => close_menu(ctx, &reply, "Nothing was added to the queue.").await,
+        Err(e) => {
+            close_menu(ctx, &reply, "Failed to add the episode to the queue.").await?;
+            Err(e)
+        }
+    }
+}
+
+/// Continue the podcast episode you listened to last
+#[instrument]
+#[poise::command(prefix_command, slash_command, rename = "resume")]
+pub async fn podcast_resume(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let last = ctx.data().podcasts.get(|podcasts| {
+        let listener = podcasts.get(&ctx.author().id)?;
+        let url = listener.last.clone()?;
+        let progress = listener.episodes.get(&url)?.clone();
+        Some((url, progress))
+    });
+    let Some((url, progress)) = last else {
+        ctx.say("You don't have a podcast episode to resume.")
+            .await?;
+        return Ok(());
+    };
+    // Joining the voice channel may take a while
+    ctx.defer().await?;
+    if !play_episode(ctx, guild_id, &url, &progress).await? {
+        return Ok(());
+    }
+
+    ctx.say(episode_added(&progress)).await?;
+
+    Ok(())
+}
+
+fn episode_added(progress: &Progress) -> String {
+    if progress.position.is_zero() {
+        format!("\"{}\" added to queue.", progress.title)
+    } else {
+        format!(
+            "\"{}\" added to queue, continuing at {}.",
+            progress.title,
+            format_duration(progress.position)
+        )
+    }
+}
+
+/// Queue a podcast episode from the given position, saving the position as it plays
+async fn play_episode(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+    url: &str,
+    progress: &Progress,
+) -> Result<bool, Error> {
+    let info = TrackInfo {
+        title: progress.title.clone(),
+        url: url.to_owned(),
+        duration: progress.duration,
+        requester: Some(ctx.author().id),
+        live: None,
+    };
+    remember_played(ctx, guild_id, &info.title, url);
+    let input = HttpRequest::new(get_http_client(ctx).await, url.to_owned()).into();
+    let Some(handles) = add_to_queue(ctx, guild_id, vec![ResolvedTrack { info, input }]).await?
+    else {
+        return Ok(false);
+    };
+
+    let user = ctx.author().id;
+    ctx.data().podcasts.update_later(|podcasts| {
+        let listener = podcasts.entry(user).or_default();
+        listener.last = Some(url.to_owned());
+        listener.episodes.insert(url.to_owned(), progress.clone());
+    });
+    for handle in handles {
+        for event in [
+            Event::Periodic(PODCAST_SAVE_INTERVAL, None),
+            Event::Track(TrackEvent::End),
+        ] {
+            let saver = PodcastProgressSaver {
+                podcasts: ctx.data().podcasts.clone(),
+                user,
+                url: url.to_owned(),
+                title: progress.title.clone(),
+                duration: progress.duration,
+            };
+            if let Err(e) = handle.add_event(event, saver) {
+                tracing::warn!(err = %e, "Failed to keep track of podcast position.");
+            }
+        }
+        if !progress.position.is_zero() {
+            player::start_at(&handle, progress.position);
+        }
+    }
+    Ok(true)
+}
+
+/// Manage the cache of downloaded songs
+#[instrument]
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("cache_stats"),
+    subcommand_required,
+    owners_only
+)]
+pub async fn cache(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show how well the cache is doing and how much space it takes
+#[instrument]
+#[poise::command(prefix_command, slash_command, rename = "stats", owners_only)]
+pub async fn cache_stats(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(cache) = &ctx.data().cache else {
+        ctx.say("The cache isn't set up on this bot.").await?;
+        return Ok(());
+    };
+
+    let stats = cache.stats();
+    let megabytes = |bytes: u64| format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0);
+    let embed = TrimmedEmbed::new()
+        .title("Cache")
+        .colour(Colour::BLURPLE)
+        .field(
+            "Hit rate",
+            format!(
+                "{:.1}% ({} of {})",
+                stats.hit_rate() * 100.0,
+                stats.hits,
+                stats.hits + stats.misses
+            ),
+            true,
+        )
+        .field("Songs", stats.entries.to_string(), true)
+        .field(
+            "Disk usage",
+            format!("{} / {}", megabytes(stats.size), megabytes(stats.max_size)),
+            true,
+        );
+    ctx.send(CreateReply::default().embed(embed.into())).await?;
+
+    Ok(())
+}
+
+/// Join a voice channel
+#[instrument]
+#[poise::command(prefix_command, aliases("votes"), slash_command)]
+pub async fn join(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+    if refuse_when_pinned(ctx, guild_id).await? {
+        return Ok(());
+    }
+    let Some(connect_to) = author_voice_channel(ctx) else {
+        ctx.say("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    let manager = get_songbird_manager(ctx).await;
+    if let Err(e) = player::connect(&manager, ctx.data(), guild_id, connect_to).await {
+        tracing::error!(err = %e, "Failed to join channel.");
+        ctx.say("Failed to join channel.").await?;
+        return Err(Box::new(e));
+    }
+
+    ctx.say("Ready to play").await?;
+    Ok(())
+}
+
+/// Tell the user the bot can't be moved while 24/7 mode is on, returning whether it is on
+async fn refuse_when_pinned(ctx: Context<'_>, guild_id: GuildId) -> Result<bool, Error> {
+    let Some(channel_id) = always_on::pinned_channel(ctx.data(), guild_id) else {
+        return Ok(false);
+    };
+    ctx.say(format!(
+        "Staying in <#{}> since 24/7 mode is on, turn it off with /247 first.",
+        channel_id
+    ))
+    .await?;
+    Ok(true)
+}
+
+/// The voice channel the author of the command is in
+fn author_voice_channel(ctx: Context<'_>) -> Option<ChannelId> {
+    ctx.guild()?
+        .voice_states
+        .get(&ctx.author().id)
+        .and_then(|voice_state| voice_state.channel_id)
+}
+
+/// Get the call to play in, joining the voice channel of the author if the bot isn't busy
+/// somewhere else. Tells the user and returns None when there is nowhere to play.
+async fn voice_call(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+) -> Result<Option<Arc<Mutex<Call>>>, Error> {
+    let manager = get_songbird_manager(ctx).await;
+    let author_channel = author_voice_channel(ctx);
+    let current = match manager.get(guild_id) {
+        Some(call_lock) => {
+            let call = call_lock.lock().await;
+            let channel = call
+                .current_channel()
+                .map(|channel| ChannelId::new(channel.0.get()));
+            Some((call_lock.clone(), channel, !call.queue().is_empty()))
+        }
+        None => None,
+    };
+
+    let pinned = always_on::pinned_channel(ctx.data(), guild_id);
+    let connect_to = match (current, pinned, author_channel) {
+        (Some((call_lock, Some(channel), busy)), None, Some(author_channel))
+            if channel != author_channel =>
+        {
+            if busy {
+                ctx.say(format!(
+                    "Already playing in <#{}>, join that channel or wait for the queue to finish.",
+                    channel
+                ))
+                .await?;
+                return Ok(None);
+            }
+            drop(call_lock);
+            author_channel
+        }
+        (Some((call_lock, Some(_), _)), _, _) => return Ok(Some(call_lock)),
+        // In 24/7 mode the bot only ever plays in the pinned channel
+        (_, Some(pinned), _) => {
+            let call_lock =
+                always_on::connect(ctx.serenity_context(), ctx.data(), guild_id, pinned).await;
+            if call_lock.is_none() {
+                ctx.say(format!("Failed to join <#{}>.", pinned)).await?;
+            }
+            return Ok(call_lock);
+        }
+        (_, None, Some(author_channel)) => author_channel,
+        (_, None, None) => {
+            ctx.say("Join a voice channel first, so I know where to play.")
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    match player::connect(&manager, ctx.data(), guild_id, connect_to).await {
+        Ok(call_lock) => Ok(Some(call_lock)),
+        Err(e) => {
+            tracing::error!(err = %e, "Failed to join channel.");
+            ctx.say("Failed to join your voice channel.").await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Leave the current voice channel
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    if refuse_when_pinned(ctx, guild_id).await? {
+        return Ok(());
+    }
+
+    let manager = get_songbird_manager(ctx).await;
+    let has_handler = manager.get(guild_id).is_some();
+
+    if has_handler {
+        if let Err(e) = player::disconnect(&manager, ctx.data(), guild_id).await {
+            ctx.say(format!("Failed: {:?}", e)).await?;
+        }
+
+        ctx.say("Left voice channel").await?;
+    } else {
+        ctx.say("Not in a voice channel").await?;
+    }
+
+    Ok(())
+}
+
+/// Move along with a user whenever they switch voice channels
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn follow(
+    ctx: Context<'_>,
+    #[description = "User to follow"] user: User,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+    if user.bot {
+        ctx.say("Can't follow a bot.").await?;
+        return Ok(());
+    }
+    if refuse_when_pinned(ctx, guild_id).await? {
+        return Ok(());
+    }
+    let user_channel = ctx.guild().and_then(|guild| {
+        guild
+            .voice_states
+            .get(&user.id)
+            .and_then(|voice_state| voice_state.channel_id)
+    });
+    let Some(channel_id) = user_channel else {
+        ctx.say(format!("<@{}> isn't in a voice channel.", user.id))
+            .await?;
+        return Ok(());
+    };
+
+    let manager = get_songbird_manager(ctx).await;
+    if let Err(e) = player::connect(&manager, ctx.data(), guild_id, channel_id).await {
+        tracing::error!(err = %e, "Failed to join channel.");
+        ctx.say("Failed to join their voice channel.").await?;
+        return Ok(());
+    }
+    ctx.data()
+        .guild_states
+        .update(guild_id, |state| state.following = Some(user.id));
+
+    ctx.say(format!(
+        "Following <@{}>, I'll move along when they switch channels.",
+        user.id
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Stop moving along with the followed user
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn unfollow(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let followed = ctx
+        .data()
+        .guild_states
+        .update(guild_id, |state| state.following.take());
+    match followed {
+        Some(user_id) => {
+            ctx.say(format!("Stopped following <@{}>.", user_id))
+                .await?
+        }
+        None => ctx.say("Not following anyone.").await?,
+    };
+    Ok(())
+}
+
+/// Rejoin the voice channel and bring back the queue from before the bot restarted
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn restore(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+    let saved_channel = ctx
+        .data()
+        .saved_queues
+        .get(|queues| queues.get(&guild_id).map(|saved| saved.voice_channel));
+    let Some(channel_id) = saved_channel else {
+        ctx.say("There is no saved queue to restore.").await?;
+        return Ok(());
+    };
+    // The pinned channel gets the saved queue back by itself
+    if refuse_when_pinned(ctx, guild_id).await? {
+        return Ok(());
+    }
+
+    let manager = get_songbird_manager(ctx).await;
+    if let Some(call_lock) = manager.get(guild_id) {
+        if !call_lock.lock().await.queue().is_empty() {
+            ctx.say("Already playing, the saved queue is the one playing now.")
+                .await?;
+            return Ok(());
+        }
+    }
+    let call_lock = match player::connect(&manager, ctx.data(), guild_id, channel_id).await {
+        Ok(call_lock) => call_lock,
+        Err(e) => {
+            tracing::error!(err = %e, "Failed to join channel.");
+            ctx.say("Failed to join the voice channel of the saved queue.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let restored = saved_queue::restore(ctx.data(), guild_id, &call_lock).await;
+    ctx.say(format!("Restored {} songs in <#{}>.", restored, channel_id))
+        .await?;
+    Ok(())
+}
+
+/// Keep the bot in your voice channel around the clock, or let it leave when idle again
+#[instrument]
+#[poise::command(
+    prefix_command,
+    slash_command,
+    rename = "247",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn always_on(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    if always_on::pinned_channel(ctx.data(), guild_id).is_some() {
+        ctx.data().settings.update_later(|settings| {
+            settings.entry(guild_id).or_default().always_on_channel = None;
+        });
+        ctx.say("24/7 mode is off, I'll leave when nothing is playing.")
+            .await?;
+        return Ok(());
+    }
+    let Some(channel_id) = author_voice_channel(ctx) else {
+        ctx.say("Join the voice channel I should stay in first.")
+            .await?;
+        return Ok(());
+    };
+
+    // Looking up the fallback playlist takes a while
+    ctx.defer().await?;
+    if always_on::connect(ctx.serenity_context(), ctx.data(), guild_id, channel_id)
+        .await
+        .is_none()
+    {
+        ctx.say("Failed to join your voice channel.").await?;
+        return Ok(());
+    }
+    ctx.data().settings.update_later(|settings| {
+        settings.entry(guild_id).or_default().always_on_channel = Some(channel_id);
+    });
+    ctx.data().guild_states.update(guild_id, |state| {
+        state.following = None;
+        state.alone_since = None;
+        state.idle_since = None;
+    });
+
+    ctx.say(format!(
+        "24/7 mode is on, I'll stay in <#{}> even after restarts.",
+        channel_id
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Number of songs shown on each page of the queue
+const QUEUE_PAGE_SIZE: usize = 10;
+
+/// Show the current queue
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn queue(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not in a voice channel, no queue to show.").await?;
+        return Ok(());
+    };
+    let (queue, current_uuid) = {
+        let driver = driver_lock.lock().await;
+        let current_uuid = driver.queue().current().map(|h| h.uuid());
+        (driver.queue().current_queue(), current_uuid)
+    };
+    if queue.is_empty() {
+        ctx.say("Queue is empty.").await?;
+        return Ok(());
+    }
+    let loop_mode = ctx.data().guild_states.get(guild_id, |s| s.loop_mode);
+
+    let infos = join_all(queue.iter().map(TrackInfo::from_handle)).await;
+    let total: Duration = infos.iter().filter_map(|info| info.duration).sum();
+    let title = format!(
+        "Queue: {} songs, {} total (loop: {})",
+        infos.len(),
+        format_duration(total),
+        loop_mode.name()
+    );
+    let lines = queue
+        .iter()
+        .zip(infos)
+        .enumerate()
+        .map(|(i, (handle, info))| {
+            let current = if Some(handle.uuid()) == current_uuid {
+                " (currently playing)"
+            } else {
+                ""
+            };
+            format!(
+                "`{}.` {} `{}`{}",
+                i + 1,
+                info.link(),
+                info.display_duration(),
+                current
+            )
+        })
+        .collect::<Vec<_>>();
+    let pages = lines
+        .chunks(QUEUE_PAGE_SIZE)
+        .map(|page| page.join("\n"))
+        .collect::<Vec<_>>();
+    let page_embed = |page: usize| -> CreateEmbed {
+        TrimmedEmbed::new()
+            .title(&title)
+            .description(&pages[page])
+            .colour(Colour::BLURPLE)
+            .footer(format!("Page {}/{}", page + 1, pages.len()))
+            .into()
+    };
+
+    if pages.len() == 1 {
+        ctx.send(CreateReply::default().embed(page_embed(0)))
+            .await?;
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let [first_id, prev_id, next_id, last_id] =
+        ["first", "prev", "next", "last"].map(|name| format!("{}{}", ctx_id, name));
+    let buttons = |page: usize| {
+        let at_start = page == 0;
+        let at_end = page + 1 == pages.len();
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(&first_id).emoji('⏮').disabled(at_start),
+            CreateButton::new(&prev_id).emoji('◀').disabled(at_start),
+            CreateButton::new(&next_id).emoji('▶').disabled(at_end),
+            CreateButton::new(&last_id).emoji('⏭').disabled(at_end),
+        ])]
+    };
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(page_embed(0))
+                .components(buttons(0)),
+        )
+        .await?;
+
+    let mut page: usize = 0;
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(Duration::from_secs(5 * 60))
+        .await
+    {
+        let id = &press.data.custom_id;
+        if *id == first_id {
+            page = 0;
+        } else if *id == prev_id {
+            page = page.saturating_sub(1);
+        } else if *id == next_id {
+            page = (page + 1).min(pages.len() - 1);
+        } else if *id == last_id {
+            page = pages.len() - 1;
+        } else {
+            continue;
+        }
+
+        press
+            .create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .embed(page_embed(page))
+                        .components(buttons(page)),
+                ),
+            )
+            .await?;
+    }
+
+    // Remove the buttons once nobody can use them anymore
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .embed(page_embed(page))
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Skip over the current song
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn skip(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("No playing anything, can't skip.").await?;
+        return Ok(());
+    };
+    let mut driver = driver_lock.lock().await;
+    let loop_mode = ctx.data().guild_states.get(guild_id, |s| s.loop_mode);
+    if let (LoopMode::Queue, Some(current)) = (loop_mode, driver.queue().current()) {
+        // Skipped songs stay in the loop, they are just moved to the back of the queue
+        let volume = f32::from(ctx.data().guild_volume(guild_id)) / 100.0;
+        player::requeue(&mut driver, &ctx.data().resolvers, &current, volume).await;
+    }
+    driver.queue().skip()?;
+    ctx.say("Skipping to the next song.").await?;
+
+    Ok(())
+}
+
+/// Pause the current song
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn pause(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not playing anything, can't pause.").await?;
+        return Ok(());
+    };
+    let Some(handle) = driver_lock.lock().await.queue().current() else {
+        ctx.say("Not playing anything, can't pause.").await?;
+        return Ok(());
+    };
+    handle.pause()?;
+    ctx.say(format!(
+        "Paused \"{}\".",
+        TrackInfo::from_handle(&handle).await.title
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Resume the current song
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn resume(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not in a voice channel, nothing to resume.")
+            .await?;
+        return Ok(());
+    };
+    let Some(handle) = driver_lock.lock().await.queue().current() else {
+        ctx.say("Queue is empty, nothing to resume.").await?;
+        return Ok(());
+    };
+    handle.play()?;
+    ctx.say(format!(
+        "Resumed \"{}\".",
+        TrackInfo::from_handle(&handle).await.title
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Stop playing and clear the queue
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not playing anything, can't stop.").await?;
+        return Ok(());
+    };
+    let current = {
+        let driver = driver_lock.lock().await;
+        let current = driver.queue().current();
+        driver.queue().stop();
+        current
+    };
+    save_queue(ctx, guild_id, &driver_lock).await;
+    match current {
+        Some(handle) => {
+            ctx.say(format!(
+                "Stopped \"{}\" and cleared the queue.",
+                TrackInfo::from_handle(&handle).await.title
+            ))
+            .await?;
+        }
+        None => {
+            ctx.say("Queue is already empty.").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Seek to a position in the current song
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn seek(
+    ctx: Context<'_>,
+    #[description = "Position like 1:23 or 1:02:03, or an offset like +30 or -10"] position: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let target = match parse_seek(&position) {
+        Ok(target) => target,
+        Err(e) => {
+            ctx.say(format!(
+                "Invalid position \"{}\": {}. Try something like 1:23, +30 or -10.",
+                position, e
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not playing anything, can't seek.").await?;
+        return Ok(());
+    };
+    let Some(handle) = driver_lock.lock().await.queue().current() else {
+        ctx.say("Not playing anything, can't seek.").await?;
+        return Ok(());
+    };
+    if TrackInfo::from_handle(&handle).await.live.is_some() {
+        ctx.say("Can't seek in a live stream.").await?;
+        return Ok(());
+    }
+
+    let current = match handle.get_info().await {
+        Ok(info) => info.position,
+        Err(ControlError::Finished) => {
+            ctx.say("The song has already ended.").await?;
+            return Ok(());
+        }
+        Err(e) => return Err(Box::new(e)),
+    };
+    let new_position = match target.resolve(current) {
+        Ok(position) => position,
+        Err(e) => {
+            ctx.say(format!("Invalid position \"{}\": {}.", position, e))
+                .await?;
+            return Ok(());
+        }
+    };
+    let duration = handle
+        .typemap()
+        .read()
+        .await
+        .get::<SongDurationKey>()
+        .cloned();
+    if let Some(duration) = duration {
+        if new_position >= duration {
+            ctx.say(format!(
+                "Can't seek to {}, the song is only {} long.",
+                format_duration(new_position),
+                format_duration(duration)
+            ))
+            .await?;
+            return Ok(());
+        }
+    }
+
+    match player::seek(&handle, new_position).await {
+        Ok(position) => {
+            ctx.say(format!("Seeked to {}.", format_duration(position)))
+                .await?;
+        }
+        Err(ControlError::Play(TrackPlayError::Seek(e))) => {
+            tracing::warn!(err = %e, "Failed to seek in track.");
+            ctx.say("This song doesn't support seeking.").await?;
+        }
+        Err(ControlError::Finished) => {
+            ctx.say("The song ended before it could be seeked.").await?;
+        }
+        Err(e) => return Err(Box::new(e)),
+    }
+
+    Ok(())
+}
+
+/// Show or change the volume
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent, from 0 to 200"]
+    #[min = 0]
+    #[max = 200]
+    volume: Option<u8>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let Some(volume) = volume else {
+        let volume = ctx.data().guild_volume(guild_id);
+        ctx.say(format!("Volume is at {}%.", volume)).await?;
+        return Ok(());
+    };
+    if volume > MAX_VOLUME {
+        ctx.say("Volume has to be between 0 and 200%.").await?;
+        return Ok(());
+    }
+
+    ctx.data().settings.update_later(|settings| {
+        settings.entry(guild_id).or_default().volume = Some(volume);
+    });
+
+    let songbird = get_songbird_manager(ctx).await;
+    if let Some(driver_lock) = songbird.get(guild_id) {
+        let driver = driver_lock.lock().await;
+        for handle in driver.queue().current_queue() {
+            if let Err(e) = handle.set_volume(f32::from(volume) / 100.0) {
+                tracing::warn!(err = %e, "Failed to change volume of a track.");
+            }
+        }
+    }
+
+    ctx.say(format!("Volume set to {}%.", volume)).await?;
+
+    Ok(())
+}
+
+/// Loop the current song, the whole queue or turn looping off
+#[instrument]
+#[poise::command(prefix_command, slash_command, rename = "loop")]
+pub async fn loop_mode(
+    ctx: Context<'_>,
+    #[description = "What to loop"] mode: LoopMode,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    ctx.data()
+        .guild_states
+        .update(guild_id, |s| s.loop_mode = mode);
+
+    let songbird = get_songbird_manager(ctx).await;
+    let current = match songbird.get(guild_id) {
+        Some(driver_lock) => driver_lock.lock().await.queue().current(),
+        None => None,
+    };
+    if let Some(handle) = current {
+        let res = match mode {
+            LoopMode::Track => handle.enable_loop(),
+            LoopMode::Queue | LoopMode::Off => handle.disable_loop(),
+        };
+        if let Err(e) = res {
+            tracing::warn!(err = %e, "Failed to change looping of the current track.");
+        }
+    }
+
+    let msg = match mode {
+        LoopMode::Off => "Looping is off.",
+        LoopMode::Track => "Looping the current song.",
+        LoopMode::Queue => "Looping the queue.",
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Run an edit on the queue of the current guild, returning None if there is no queue to edit
+async fn edit_queue<O>(
+    ctx: Context<'_>,
+    f: impl FnOnce(&mut VecDeque<Queued>) -> O,
+) -> Result<Option<O>, Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(None);
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not in a voice channel, no queue to edit.").await?;
+        return Ok(None);
+    };
+    let edited = driver_lock.lock().await.queue().modify_queue(f);
+    save_queue(ctx, guild_id, &driver_lock).await;
+    Ok(Some(edited))
+}
+
+/// Remove a song from the queue
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Position of the song in the queue"] position: usize,
+) -> Result<(), Error> {
+    match edit_queue(ctx, |queue| queue_edit::remove(queue, position)).await? {
+        Some(Ok(removed)) => {
+            if let Err(e) = removed.stop() {
+                tracing::warn!(err = %e, "Failed to stop removed track.");
+            }
+            let title = TrackInfo::from_handle(&removed).await.title;
+            ctx.say(format!("Removed \"{}\" from the queue.", title))
+                .await?;
+        }
+        Some(Err(e)) => {
+            ctx.say(e.to_string()).await?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Move a song to a different position in the queue
+#[instrument]
+#[poise::command(prefix_command, slash_command, rename = "move")]
+pub async fn move_track(
+    ctx: Context<'_>,
+    #[description = "Position of the song to move"] from: usize,
+    #[description = "Position to move the song to"] to: usize,
+) -> Result<(), Error> {
+    match edit_queue(ctx, |queue| queue_edit::move_to(queue, from, to)).await? {
+        Some(Ok(())) => {
+            ctx.say(format!("Moved song {} to position {}.", from, to))
+                .await?;
+        }
+        Some(Err(e)) => {
+            ctx.say(e.to_string()).await?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Swap the positions of two songs in the queue
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn swap(
+    ctx: Context<'_>,
+    #[description = "Position of the first song"] a: usize,
+    #[description = "Position of the second song"] b: usize,
+) -> Result<(), Error> {
+    match edit_queue(ctx, |queue| queue_edit::swap(queue, a, b)).await? {
+        Some(Ok(())) => {
+            ctx.say(format!("Swapped songs {} and {}.", a, b)).await?;
+        }
+        Some(Err(e)) => {
+            ctx.say(e.to_string()).await?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Remove every song from the queue except the one playing
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(removed) = edit_queue(ctx, queue_edit::clear).await? else {
+        return Ok(());
+    };
+    for handle in &removed {
+        if let Err(e) = handle.stop() {
+            tracing::warn!(err = %e, "Failed to stop removed track.");
+        }
+    }
+    ctx.say(format!("Removed {} songs from the queue.", removed.len()))
+        .await?;
+
+    Ok(())
+}
+
+/// Shuffle the songs in the queue
+#[instrument]
+#[poise::command(prefix_command, slash_command)]
+pub async fn shuffle(ctx: Context<'_>) -> Result<(), Error> {
+    let shuffled = edit_queue(ctx, |queue| {
+        queue_edit::shuffle(queue, &mut rand::thread_rng());
+    })
+    .await?;
+    if shuffled.is_some() {
+        ctx.say("Shuffled the queue.").await?;
+    }
+
+    Ok(())
+}
+
+/// Show the song that is playing right now
+#[instrument]
+#[poise::command(prefix_command, slash_command, aliases("np"))]
+pub async fn nowplaying(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild().map(|g| g.id) else {
+        ctx.say("This command is only supported in guilds.").await?;
+        return Ok(());
+    };
+
+    let songbird = get_songbird_manager(ctx).await;
+    let Some(driver_lock) = songbird.get(guild_id) else {
+        ctx.say("Not playing anything.").await?;
+        return Ok(());
+    };
+    let Some(handle) = driver_lock.lock().await.queue().current() else {
+        ctx.say("Not playing anything.").await?;
+        return Ok(());
+    };
+    let info = TrackInfo::from_handle(&handle).await;
+    let state = match handle.get_info().await {
+        Ok(state) => state,
+        Err(ControlError::Finished) => {
+            ctx.say("The song has just ended.").await?;
+            return Ok(());
+        }
+        Err(e) => return Err(Box::new(e)),
+    };
+    let loop_mode = ctx.data().guild_states.get(guild_id, |s| s.loop_mode);
+
+    let status = if state.playing == PlayMode::Pause {
+        "⏸"
+    } else {
+        "▶"
+    };
+    let progress = match info.duration {
+        _ if info.live.is_some() => format!("{} `LIVE`", status),
+        Some(duration) => format!(
+            "{} {} `{} / {}`",
+            status,
+            progress_bar(state.position, duration, 20),
+            format_duration(state.position),
+            format_duration(duration)
+        ),
+        None => format!("{} `{}`", status, format_duration(state.position)),
+    };
+    let requester = info
+        .requester
+        .map(|user| format!("<@{}>", user))
+        .unwrap_or_else(|| "Unknown".to_owned());
+
+    let mut embed = TrimmedEmbed::new()
+        .title(info.display_title())
+        .description(progress)
+        .colour(Colour::BLURPLE)
+        .field("Loop", loop_mode.name(), true)
+        .field("Requested by", requester, true);
+    if info.url.starts_with("http") {
+        embed = embed.url(info.url);
+    }
+    ctx.send(CreateReply::default().embed(embed.into())).await?;
 
     Ok(())
 }
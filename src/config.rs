@@ -1,4 +1,4 @@
-use std::{fs, sync::Arc};
+use std::{collections::HashMap, fs, sync::Arc};
 
 use serde::Deserialize;
 
@@ -8,6 +8,30 @@ pub type Config = Arc<MainConfig>;
 pub struct MainConfig {
     pub token: String,
     pub error_webhook: Option<String>,
+    /// Volume in percent that guilds start with before choosing their own, at most 200
+    pub default_volume: Option<u8>,
+    /// Most entries that are added to the queue from a single playlist
+    pub max_playlist_entries: Option<usize>,
+    /// Credentials of a Spotify app, needed to play Spotify links
+    pub spotify_client_id: Option<String>,
+    pub spotify_client_secret: Option<String>,
+    /// Directory of music on this machine, searchable with the library command and playable
+    /// with file:// links
+    pub library_path: Option<String>,
+    /// Stream urls of the stations the radio command can play, by their name
+    #[serde(default)]
+    pub radio_stations: HashMap<String, String>,
+    /// Directory to keep downloaded YouTube songs in, inside a youtube directory of their own.
+    /// Nothing is cached without it.
+    pub cache_dir: Option<String>,
+    /// Most disk space the cache may use in megabytes, defaults to 1024
+    pub cache_max_mb: Option<u64>,
+    /// Seconds to stay in a voice channel after everyone else left, defaults to 60
+    pub alone_timeout_secs: Option<u64>,
+    /// Minutes to stay in a voice channel with nothing queued, defaults to 10
+    pub idle_timeout_mins: Option<u64>,
+    /// Song or playlist to play in 24/7 mode when nothing else is queued
+    pub fallback_playlist: Option<String>,
 }
 
 pub fn load_config() -> Config {
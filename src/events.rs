@@ -1,7 +1,25 @@
-use serenity::async_trait;
-use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
-use crate::typekeys::SongUrlKey;
+use serenity::{
+    all::{GuildId, UserId},
+    async_trait,
+};
+use songbird::{
+    tracks::PlayMode, Call, Event, EventContext, EventHandler as VoiceEventHandler, Songbird,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    guild_state::{GuildStates, LoopMode},
+    player,
+    podcast::{Podcasts, Progress},
+    resolver::Resolvers,
+    saved_queue::{self, SavedQueues},
+    typekeys::SongUrlKey,
+};
 
 pub struct TrackErrorNotifier;
 
@@ -22,3 +40,165 @@ impl VoiceEventHandler for TrackErrorNotifier {
         None
     }
 }
+
+/// Gets the next track in the queue ready shortly before a track ends, so the song after it can
+/// start right away instead of waiting for yt-dlp to look up the stream. Scheduled from where the
+/// track is whenever it starts playing, and dropped when it pauses or ends.
+pub struct PrefetchNext {
+    pub call: Weak<Mutex<Call>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for PrefetchNext {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(track_list) = ctx else {
+            return None;
+        };
+
+        for (state, handle) in *track_list {
+            match state.playing {
+                PlayMode::Play => {
+                    player::schedule_prefetch(handle, state.position, self.call.clone()).await
+                }
+                _ => player::cancel_prefetch(handle).await,
+            }
+        }
+
+        None
+    }
+}
+
+/// Seeks a track to where it should start once it first plays, so a track far back in the queue
+/// isn't readied early just to seek it
+pub struct SeekOnPlay {
+    pub position: Duration,
+}
+
+#[async_trait]
+impl VoiceEventHandler for SeekOnPlay {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(track_list) = ctx else {
+            return None;
+        };
+
+        for (_, handle) in *track_list {
+            let (handle, position) = ((*handle).clone(), self.position);
+            tokio::spawn(async move {
+                if let Err(e) = player::seek(&handle, position).await {
+                    tracing::warn!(err = %e, "Failed to seek to where the track should start.");
+                }
+            });
+        }
+
+        Some(Event::Cancel)
+    }
+}
+
+/// Applies the loop mode of a guild when tracks start and end
+pub struct LoopHandler {
+    pub guild_id: GuildId,
+    pub guild_states: Arc<GuildStates>,
+    pub manager: Arc<Songbird>,
+    pub resolvers: Arc<Resolvers>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for LoopHandler {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(track_list) = ctx else {
+            return None;
+        };
+        let loop_mode = self.guild_states.get(self.guild_id, |s| s.loop_mode);
+
+        for (state, handle) in *track_list {
+            match (loop_mode, &state.playing) {
+                (LoopMode::Track, PlayMode::Play) => {
+                    if let Err(e) = handle.enable_loop() {
+                        tracing::warn!(err = %e, "Failed to loop track.");
+                    }
+                }
+                // Only tracks that finished on their own are added back, skipped tracks are
+                // added back by the skip command and stopped ones should be gone for good.
+                (LoopMode::Queue, PlayMode::End) => {
+                    let Some(call_lock) = self.manager.get(self.guild_id) else {
+                        continue;
+                    };
+                    let (handle, resolvers, volume) =
+                        ((*handle).clone(), self.resolvers.clone(), state.volume);
+                    tokio::spawn(async move {
+                        let mut call = call_lock.lock().await;
+                        player::requeue(&mut call, &resolvers, &handle, volume).await;
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Keeps track of how far a user got in a podcast episode, so they can resume it later
+pub struct PodcastProgressSaver {
+    pub podcasts: Arc<Podcasts>,
+    pub user: UserId,
+    pub url: String,
+    pub title: String,
+    pub duration: Option<Duration>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for PodcastProgressSaver {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(track_list) = ctx else {
+            return None;
+        };
+
+        for (state, _) in *track_list {
+            self.podcasts.update_later(|podcasts| {
+                let listener = podcasts.entry(self.user).or_default();
+                if state.playing == PlayMode::End {
+                    // Listened to the end, so there is nothing left to resume
+                    listener.episodes.remove(&self.url);
+                    return;
+                }
+                let progress = Progress {
+                    title: self.title.clone(),
+                    position: state.position,
+                    duration: self.duration,
+                };
+                listener.episodes.insert(self.url.clone(), progress);
+            });
+        }
+
+        None
+    }
+}
+
+/// Writes the queue of a guild to disk as tracks start and end, and every now and then to keep
+/// the position of the current track up to date
+pub struct QueueSaver {
+    pub guild_id: GuildId,
+    pub guild_states: Arc<GuildStates>,
+    pub manager: Arc<Songbird>,
+    pub saved_queues: Arc<SavedQueues>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for QueueSaver {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let call_lock = self.manager.get(self.guild_id)?;
+        let (guild_id, guild_states, saved_queues) = (
+            self.guild_id,
+            self.guild_states.clone(),
+            self.saved_queues.clone(),
+        );
+        // Save outside of the handler so writing to disk doesn't hold up the other events, saves
+        // finishing out of order are sorted out by the change numbers in `SavedQueues`
+        tokio::spawn(async move {
+            saved_queue::save(&saved_queues, &guild_states, guild_id, &call_lock).await;
+        });
+
+        None
+    }
+}
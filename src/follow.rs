@@ -0,0 +1,44 @@
+use serenity::all::{Context as SerenityContext, GuildId, VoiceState};
+
+use crate::{auto_leave, player, Data};
+
+/// Move the bot along with the user it follows, called on every voice state update
+pub async fn on_voice_state_update(
+    ctx: &SerenityContext,
+    data: &Data,
+    guild_id: GuildId,
+    voice_state: &VoiceState,
+) {
+    let following = data.guild_states.get(guild_id, |state| state.following);
+    if following != Some(voice_state.user_id) {
+        return;
+    }
+
+    let Some(channel_id) = voice_state.channel_id else {
+        let text_channel = data.guild_states.update(guild_id, |state| {
+            state.following = None;
+            state.text_channel
+        });
+        if let Some(channel) = text_channel {
+            let notice = format!(
+                "Stopped following <@{}> since they left voice.",
+                voice_state.user_id
+            );
+            if let Err(e) = channel.say(ctx, notice).await {
+                tracing::warn!(err = %e, "Failed to say the bot stopped following.");
+            }
+        }
+        return;
+    };
+
+    if auto_leave::bot_channel(ctx, guild_id).await == Some(channel_id) {
+        return;
+    }
+    let Some(manager) = songbird::get(ctx).await else {
+        return;
+    };
+    // Joining with an existing call moves it, keeping the queue and the current track playing
+    if let Err(e) = player::connect(&manager, data, guild_id, channel_id).await {
+        tracing::error!(err = %e, "Failed to follow into channel {}.", channel_id);
+    }
+}
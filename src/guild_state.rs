@@ -0,0 +1,58 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+
+use parking_lot::Mutex;
+use serenity::all::{ChannelId, GuildId, UserId};
+
+use crate::history::HistoryEntry;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum LoopMode {
+    #[default]
+    #[name = "off"]
+    Off,
+    #[name = "track"]
+    Track,
+    #[name = "queue"]
+    Queue,
+}
+
+/// State about a guild that only lives as long as the bot is running
+#[derive(Debug, Clone, Default)]
+pub struct GuildState {
+    pub loop_mode: LoopMode,
+    /// Results of the latest searches, newest first
+    pub recent_searches: VecDeque<HistoryEntry>,
+    /// Text channel a command was last used in, where notices from the bot are posted
+    pub text_channel: Option<ChannelId>,
+    /// When everyone else left the voice channel of the bot
+    pub alone_since: Option<Instant>,
+    /// When the queue became empty
+    pub idle_since: Option<Instant>,
+    /// User the bot moves along with between voice channels
+    pub following: Option<UserId>,
+    /// Whether the queue is being refilled for 24/7 mode right now
+    pub refilling: bool,
+    /// When the fallback playlist last failed to load, so it isn't tried again right away
+    pub fallback_failed_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct GuildStates {
+    states: Mutex<HashMap<GuildId, GuildState>>,
+}
+
+impl GuildStates {
+    pub fn get<R>(&self, guild_id: GuildId, f: impl FnOnce(&GuildState) -> R) -> R {
+        match self.states.lock().get(&guild_id) {
+            Some(state) => f(state),
+            None => f(&GuildState::default()),
+        }
+    }
+
+    pub fn update<R>(&self, guild_id: GuildId, f: impl FnOnce(&mut GuildState) -> R) -> R {
+        f(self.states.lock().entry(guild_id).or_default())
+    }
+}
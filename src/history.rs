@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use serenity::all::GuildId;
+
+use crate::storage::Storage;
+
+pub type History = Storage<HashMap<GuildId, VecDeque<HistoryEntry>>>;
+
+pub const HISTORY_PATH: &str = "./data/history.json";
+
+/// How many songs are remembered for each guild
+pub const MAX_HISTORY: usize = 100;
+
+/// A song that was played or found recently, used to suggest songs to play
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub title: String,
+    pub url: String,
+}
+
+/// Put the entry at the front, removing an older entry for the same song
+pub fn record(entries: &mut VecDeque<HistoryEntry>, entry: HistoryEntry, max: usize) {
+    entries.retain(|e| e.url != entry.url);
+    entries.push_front(entry);
+    entries.truncate(max);
+}
+
+/// Find up to `limit` entries whose title or url contains `partial`, ignoring case and duplicates
+pub fn suggestions<'a>(
+    entries: impl IntoIterator<Item = &'a HistoryEntry>,
+    partial: &str,
+    limit: usize,
+) -> Vec<&'a HistoryEntry> {
+    let partial = partial.to_lowercase();
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|e| {
+            e.title.to_lowercase().contains(&partial) || e.url.to_lowercase().contains(&partial)
+        })
+        .filter(|e| seen.insert(e.url.as_str()))
+        .take(limit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, url: &str) -> HistoryEntry {
+        HistoryEntry {
+            title: title.to_owned(),
+            url: url.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut entries = VecDeque::new();
+        record(&mut entries, entry("A", "a"), 2);
+        record(&mut entries, entry("B", "b"), 2);
+        record(&mut entries, entry("A again", "a"), 2);
+        assert_eq!(entries, [entry("A again", "a"), entry("B", "b")]);
+        record(&mut entries, entry("C", "c"), 2);
+        assert_eq!(entries, [entry("C", "c"), entry("A again", "a")]);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let searches = [entry(
+            "Never Gonna Give You Up",
+            "https://youtu.be/dQw4w9WgXcQ",
+        )];
+        let history = [
+            entry("Darude - Sandstorm", "https://youtu.be/y6120QOlsfU"),
+            entry("Never Gonna Give You Up", "https://youtu.be/dQw4w9WgXcQ"),
+        ];
+        let all = || searches.iter().chain(history.iter());
+
+        assert_eq!(suggestions(all(), "", 25).len(), 2);
+        assert_eq!(suggestions(all(), "", 1), [&searches[0]]);
+        assert_eq!(suggestions(all(), "sAnD", 25), [&history[0]]);
+        assert_eq!(suggestions(all(), "dQw4", 25), [&searches[0]]);
+        assert!(suggestions(all(), "nothing", 25).is_empty());
+    }
+}
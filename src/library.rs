@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+use reqwest::Url;
+
+use crate::{
+    player::TrackInfo,
+    resolver::{file_title, read_file_tags, AudioTags, AUDIO_EXTENSIONS},
+};
+
+/// A song found in the library directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryTrack {
+    pub id: usize,
+    pub path: PathBuf,
+    pub tags: AudioTags,
+}
+
+impl LibraryTrack {
+    /// Title from the tags, or the file name if it doesn't have any
+    pub fn title(&self) -> String {
+        self.tags
+            .display_title()
+            .unwrap_or_else(|| file_title(&self.path))
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.tags.duration
+    }
+
+    /// The track as it is stored in the queue, with a file:// url so it can be played again
+    pub fn info(&self) -> TrackInfo {
+        TrackInfo {
+            title: self.title(),
+            url: Url::from_file_path(&self.path)
+                .map(String::from)
+                .unwrap_or_else(|_| self.path.display().to_string()),
+            duration: self.duration(),
+            requester: None,
+            live: None,
+        }
+    }
+
+    /// Text that searches are matched against
+    fn search_text(&self) -> String {
+        [
+            self.tags.title.as_deref(),
+            self.tags.artist.as_deref(),
+            self.tags.album.as_deref(),
+            self.path.file_name().and_then(|name| name.to_str()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+    }
+}
+
+/// Index of the songs in a directory on the machine the bot runs on
+#[derive(Debug)]
+pub struct Library {
+    root: PathBuf,
+    tracks: RwLock<Vec<LibraryTrack>>,
+}
+
+impl Library {
+    /// Create an empty library, call `rescan` to fill it
+    pub fn new(root: impl Into<PathBuf>) -> Library {
+        Library {
+            root: root.into(),
+            tracks: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Read the tags of every song in the directory again, returning how many were found. Songs
+    /// that were already there keep their number.
+    pub async fn rescan(&self) -> io::Result<usize> {
+        let root = self.root.clone();
+        let found = tokio::task::spawn_blocking(move || scan(&root))
+            .await
+            .map_err(io::Error::other)??;
+        let mut tracks = self.tracks.write();
+        *tracks = number_tracks(&tracks, found);
+        Ok(tracks.len())
+    }
+
+    pub fn get(&self, id: usize) -> Option<LibraryTrack> {
+        self.tracks.read().iter().find(|t| t.id == id).cloned()
+    }
+
+    /// Find the songs matching all the words of the query
+    pub fn search(&self, query: &str, limit: usize) -> Vec<LibraryTrack> {
+        search(&self.tracks.read(), query, limit)
+    }
+}
+
+fn search(tracks: &[LibraryTrack], query: &str, limit: usize) -> Vec<LibraryTrack> {
+    let words = query.to_lowercase();
+    let words = words.split_whitespace().collect::<Vec<_>>();
+    tracks
+        .iter()
+        .filter(|track| {
+            let text = track.search_text();
+            words.iter().all(|word| text.contains(word))
+        })
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+/// Find all the audio files below the root and read their tags, in the order of their paths
+fn scan(root: &Path) -> io::Result<Vec<(PathBuf, AudioTags)>> {
+    let mut paths = Vec::new();
+    find_audio_files(root, &mut paths)?;
+    paths.sort();
+
+    let mut found = Vec::with_capacity(paths.len());
+    for path in paths {
+        match read_file_tags(&path) {
+            Ok(tags) => found.push((path, tags)),
+            Err(e) => tracing::warn!(err = %e, "Skipping \"{}\" in the library.", path.display()),
+        }
+    }
+    Ok(found)
+}
+
+/// Number the songs found by a scan, keeping the numbers of songs that were already in the
+/// library so numbers from an earlier search still play the same song
+fn number_tracks(previous: &[LibraryTrack], found: Vec<(PathBuf, AudioTags)>) -> Vec<LibraryTrack> {
+    let ids = previous
+        .iter()
+        .map(|track| (track.path.as_path(), track.id))
+        .collect::<HashMap<_, _>>();
+    let mut last_id = previous.iter().map(|track| track.id).max().unwrap_or(0);
+    let mut tracks = Vec::with_capacity(found.len());
+    for (path, tags) in found {
+        let id = match ids.get(path.as_path()) {
+            Some(&id) => id,
+            None => {
+                last_id += 1;
+                last_id
+            }
+        };
+        tracks.push(LibraryTrack { id, path, tags });
+    }
+    tracks
+}
+
+fn find_audio_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // Unlike Path::is_dir this doesn't follow symlinks, so a link back up the tree can't
+        // make the scan go around in circles
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if let Err(e) = find_audio_files(&path, paths) {
+                tracing::warn!(err = %e, "Failed to read \"{}\" in the library.", path.display());
+            }
+            continue;
+        }
+        if file_type.is_symlink() && path.is_dir() {
+            tracing::debug!("Not following the directory link \"{}\".", path.display());
+            continue;
+        }
+        let is_audio = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        if is_audio {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: usize, file: &str, title: Option<&str>, artist: Option<&str>) -> LibraryTrack {
+        LibraryTrack {
+            id,
+            path: PathBuf::from("/music").join(file),
+            tags: AudioTags {
+                title: title.map(str::to_owned),
+                artist: artist.map(str::to_owned),
+                album: Some("Greatest Hits".to_owned()),
+                duration: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let tracks = [
+            track(1, "01.mp3", Some("Take On Me"), Some("a-ha")),
+            track(2, "02.mp3", Some("Africa"), Some("Toto")),
+            track(3, "Rosanna.flac", None, None),
+        ];
+        let ids = |query: &str| {
+            search(&tracks, query, 10)
+                .iter()
+                .map(|t| t.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("toto"), vec![2]);
+        assert_eq!(ids("A-HA take"), vec![1]);
+        assert_eq!(ids("rosanna"), vec![3]);
+        assert_eq!(ids("greatest"), vec![1, 2, 3]);
+        assert_eq!(ids("toto take"), Vec::<usize>::new());
+        assert_eq!(search(&tracks, "", 2).len(), 2);
+
+        assert_eq!(tracks[0].title(), "a-ha - Take On Me");
+        assert_eq!(tracks[2].title(), "Rosanna");
+        assert_eq!(tracks[2].info().url, "file:///music/Rosanna.flac");
+    }
+
+    #[test]
+    fn test_numbers_stay_the_same() {
+        let found = |files: &[&str]| {
+            files
+                .iter()
+                .map(|file| (PathBuf::from("/music").join(file), AudioTags::default()))
+                .collect::<Vec<_>>()
+        };
+        let ids = |tracks: &[LibraryTrack]| tracks.iter().map(|t| t.id).collect::<Vec<_>>();
+
+        let first = number_tracks(&[], found(&["a.mp3", "b.mp3", "c.mp3"]));
+        assert_eq!(ids(&first), vec![1, 2, 3]);
+
+        // A new song sorted before the others doesn't shift their numbers
+        let second = number_tracks(&first, found(&["0.mp3", "a.mp3", "c.mp3"]));
+        assert_eq!(ids(&second), vec![4, 1, 3]);
+        assert_eq!(second[2].path, PathBuf::from("/music/c.mp3"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop() {
+        let dir = std::env::temp_dir().join(format!("music_bot_library_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("album")).unwrap();
+        std::fs::write(dir.join("album/song.mp3"), b"").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("album/loop")).unwrap();
+
+        let mut paths = Vec::new();
+        find_audio_files(&dir, &mut paths).unwrap();
+        assert_eq!(paths, vec![dir.join("album/song.mp3")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
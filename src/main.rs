@@ -2,13 +2,22 @@ use std::sync::Arc;
 
 use reqwest::Client as HttpClient;
 
-use serenity::{all::Http, prelude::GatewayIntents};
+use serenity::{
+    all::{FullEvent, GuildId, Http},
+    prelude::GatewayIntents,
+};
 use songbird::SerenityInit;
 
 use tracing::level_filters::LevelFilter;
-use tracing_appender;
 use tracing_subscriber::{layer::SubscriberExt, Layer, Registry};
 
+mod always_on;
+
+mod auto_leave;
+
+mod cache;
+use cache::AudioCache;
+
 mod config;
 use config::{load_config, Config};
 
@@ -16,6 +25,42 @@ mod commands;
 
 mod events;
 
+mod follow;
+
+mod guild_state;
+use guild_state::GuildStates;
+
+mod history;
+use history::{History, HISTORY_PATH};
+
+mod library;
+use library::Library;
+
+mod player;
+
+mod podcast;
+use podcast::{Podcasts, PODCASTS_PATH};
+
+mod queue_edit;
+
+mod resolver;
+use resolver::{
+    FileResolver, HttpResolver, RadioResolver, Resolvers, SpotifyTrackResolver, YoutubeResolver,
+};
+
+mod saved_queue;
+use saved_queue::{SavedQueues, SAVED_QUEUES_PATH};
+
+mod settings;
+use settings::{Settings, SETTINGS_PATH};
+
+mod spotify;
+use spotify::{SpotifyApi, SpotifyResolver};
+
+mod storage;
+
+mod timestamp;
+
 mod trimmed_embed;
 
 mod typekeys;
@@ -26,7 +71,33 @@ mod tracing_webhook;
 #[derive(Debug, Clone)]
 struct Data {
     config: Config,
+    settings: Arc<Settings>,
+    guild_states: Arc<GuildStates>,
+    history: Arc<History>,
+    resolvers: Arc<Resolvers>,
+    library: Option<Arc<Library>>,
+    podcasts: Arc<Podcasts>,
+    cache: Option<Arc<AudioCache>>,
+    saved_queues: Arc<SavedQueues>,
 }
+
+impl Data {
+    /// Volume in percent that tracks in this guild should play at
+    fn guild_volume(&self, guild_id: GuildId) -> u8 {
+        self.settings
+            .get(|settings| settings.get(&guild_id).and_then(|s| s.volume))
+            .or(self.config.default_volume)
+            .unwrap_or(100)
+            .min(MAX_VOLUME)
+    }
+}
+
+/// Loudest volume in percent, anything above it clips
+const MAX_VOLUME: u8 = 200;
+
+/// Size limit of the cache in megabytes when the config doesn't set one
+const DEFAULT_CACHE_MAX_MB: u64 = 1024;
+
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
@@ -37,6 +108,13 @@ async fn get_songbird_manager(ctx: Context<'_>) -> Arc<songbird::Songbird> {
         .clone()
 }
 
+async fn get_http_client(ctx: Context<'_>) -> HttpClient {
+    let data = ctx.serenity_context().data.read().await;
+    data.get::<HttpKey>()
+        .cloned()
+        .expect("Guaranteed to exist in the typemap.")
+}
+
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
     // This is our custom error handler
     // They are many errors that can occur, so we only handle the ones we want to customize
@@ -59,6 +137,38 @@ async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
     }
 }
 
+/// Remember where commands are used, so notices from the bot end up in the same channel
+async fn pre_command(ctx: Context<'_>) {
+    if let Some(guild_id) = ctx.guild_id() {
+        ctx.data().guild_states.update(guild_id, |state| {
+            state.text_channel = Some(ctx.channel_id())
+        });
+    }
+}
+
+async fn event_handler(
+    ctx: &serenity::all::Context,
+    event: &FullEvent,
+    _framework: poise::FrameworkContext<'_, Data, Error>,
+    data: &Data,
+) -> Result<(), Error> {
+    match event {
+        FullEvent::Ready { data_about_bot } => {
+            always_on::on_ready(ctx, data, data_about_bot).await;
+            saved_queue::on_ready(ctx, data, data_about_bot).await;
+        }
+        FullEvent::VoiceStateUpdate { new, .. } => {
+            if let Some(guild_id) = new.guild_id {
+                always_on::on_voice_state_update(ctx, data, guild_id, new).await;
+                follow::on_voice_state_update(ctx, data, guild_id, new).await;
+                auto_leave::on_voice_state_update(ctx, data, guild_id).await;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
     let config = load_config();
@@ -85,11 +195,34 @@ async fn main() {
     let options = poise::FrameworkOptions {
         commands: vec![
             commands::help(),
+            commands::always_on(),
             commands::join(),
+            commands::cache(),
+            commands::clear(),
+            commands::follow(),
             commands::leave(),
+            commands::library(),
+            commands::loop_mode(),
+            commands::move_track(),
+            commands::nowplaying(),
+            commands::pause(),
             commands::play(),
+            commands::play_file(),
+            commands::play_message_attachments(),
+            commands::podcast(),
             commands::queue(),
+            commands::radio(),
+            commands::remove(),
+            commands::restore(),
+            commands::resume(),
+            commands::search(),
+            commands::seek(),
+            commands::shuffle(),
             commands::skip(),
+            commands::stop(),
+            commands::swap(),
+            commands::unfollow(),
+            commands::volume(),
         ],
         prefix_options: poise::PrefixFrameworkOptions {
             prefix: Some("=".to_owned()),
@@ -97,15 +230,82 @@ async fn main() {
             ..Default::default()
         },
         on_error: |error| Box::pin(on_error(error)),
+        pre_command: |ctx| Box::pin(pre_command(ctx)),
+        event_handler: |ctx, event, framework, data| {
+            Box::pin(event_handler(ctx, event, framework, data))
+        },
         ..Default::default()
     };
 
+    let http_client = HttpClient::new();
+    let spotify = match (&config.spotify_client_id, &config.spotify_client_secret) {
+        (Some(id), Some(secret)) => Some(Arc::new(SpotifyApi::new(
+            http_client.clone(),
+            id.clone(),
+            secret.clone(),
+        )) as Arc<dyn SpotifyResolver>),
+        _ => None,
+    };
+
+    let cache = config.cache_dir.as_ref().and_then(|dir| {
+        let max_size = config.cache_max_mb.unwrap_or(DEFAULT_CACHE_MAX_MB) * 1024 * 1024;
+        match AudioCache::open(dir, max_size) {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(e) => {
+                tracing::error!(err = %e, "Failed to open the cache, songs won't be cached.");
+                None
+            }
+        }
+    });
+
+    // The first resolver that handles a query gets it, so searching YouTube has to come last
+    let mut resolvers =
+        Resolvers::new().with(SpotifyTrackResolver::new(spotify, http_client.clone()));
+    if let Some(library_path) = &config.library_path {
+        resolvers = resolvers.with(FileResolver::new(library_path));
+    }
+    let resolvers = Arc::new(
+        resolvers
+            .with(RadioResolver::new(
+                http_client.clone(),
+                &config.radio_stations,
+            ))
+            .with(HttpResolver::new(http_client.clone()))
+            .with(YoutubeResolver::new(http_client.clone(), cache.clone())),
+    );
+
+    let library = config
+        .library_path
+        .as_ref()
+        .map(|path| Arc::new(Library::new(path)));
+
     let framework = poise::Framework::builder()
         .setup(move |ctx, ready, framework| {
             Box::pin(async move {
                 println!("Logged in as {}", ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data { config })
+                if let Some(library) = library.clone() {
+                    // Reading the tags of a big library takes a while, so it happens in the background
+                    tokio::spawn(async move {
+                        match library.rescan().await {
+                            Ok(count) => tracing::info!("Found {} songs in the library.", count),
+                            Err(e) => tracing::error!(err = %e, "Failed to scan the library."),
+                        }
+                    });
+                }
+                let data = Data {
+                    config,
+                    settings: Arc::new(Settings::load(SETTINGS_PATH)),
+                    guild_states: Arc::new(GuildStates::default()),
+                    history: Arc::new(History::load(HISTORY_PATH)),
+                    resolvers,
+                    library,
+                    podcasts: Arc::new(Podcasts::load(PODCASTS_PATH)),
+                    cache,
+                    saved_queues: Arc::new(SavedQueues::load(SAVED_QUEUES_PATH)),
+                };
+                auto_leave::spawn_idle_checker(ctx.clone(), data.clone());
+                Ok(data)
             })
         })
         .options(options)
@@ -115,7 +315,7 @@ async fn main() {
 
     let client = serenity::client::Client::builder(&config_clone.token, intents)
         .framework(framework)
-        .type_map_insert::<HttpKey>(HttpClient::new())
+        .type_map_insert::<HttpKey>(http_client)
         .register_songbird()
         .await;
 
@@ -0,0 +1,295 @@
+use std::{
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use serenity::all::{ChannelId, GuildId, UserId};
+use songbird::{
+    error::JoinResult,
+    input::Input,
+    tracks::{ControlError, PlayMode, Track, TrackHandle},
+    Call, Event, Songbird, TrackEvent,
+};
+use tokio::{sync::Mutex, task::AbortHandle};
+use tracing::Instrument;
+
+use crate::{
+    events::{LoopHandler, PrefetchNext, QueueSaver, SeekOnPlay, TrackErrorNotifier},
+    resolver::{Resolvers, StreamTitle},
+    saved_queue,
+    timestamp::format_duration,
+    trimmed_embed::escape_markdown,
+    typekeys::{
+        PrefetchKey, SongDurationKey, SongLiveKey, SongRequesterKey, SongTitleKey, SongUrlKey,
+    },
+    Data,
+};
+
+/// The metadata we keep about every track in the typemap of its handle
+#[derive(Debug, Clone)]
+pub struct TrackInfo {
+    pub title: String,
+    pub url: String,
+    pub duration: Option<Duration>,
+    pub requester: Option<UserId>,
+    /// Set for endless live streams, with the title of what they are playing right now
+    pub live: Option<StreamTitle>,
+}
+
+impl TrackInfo {
+    /// Read the metadata back out of a track handle
+    pub async fn from_handle(handle: &TrackHandle) -> TrackInfo {
+        let typemap = handle.typemap().read().await;
+        TrackInfo {
+            title: typemap
+                .get::<SongTitleKey>()
+                .cloned()
+                .unwrap_or_else(|| "Unknown".to_owned()),
+            url: typemap
+                .get::<SongUrlKey>()
+                .cloned()
+                .unwrap_or_else(|| "Unknown".to_owned()),
+            duration: typemap.get::<SongDurationKey>().cloned(),
+            requester: typemap.get::<SongRequesterKey>().cloned(),
+            live: typemap.get::<SongLiveKey>().cloned(),
+        }
+    }
+
+    /// Title to show, with what a live stream is currently playing after the station name
+    pub fn display_title(&self) -> String {
+        match self.live.as_ref().and_then(|live| live.get()) {
+            Some(now_playing) => format!("{}: {}", self.title, now_playing),
+            None => self.title.clone(),
+        }
+    }
+
+    /// Title to show in embeds, linked to the track if it can be opened in a browser
+    pub fn link(&self) -> String {
+        let title = escape_markdown(&self.display_title());
+        if self.url.starts_with("https://") || self.url.starts_with("http://") {
+            format!("[{}]({})", title, self.url.replace(')', "%29"))
+        } else {
+            title
+        }
+    }
+
+    /// Length to show, "LIVE" for streams that never end
+    pub fn display_duration(&self) -> String {
+        match (&self.live, self.duration) {
+            (Some(_), _) => "LIVE".to_owned(),
+            (None, Some(duration)) => format_duration(duration),
+            (None, None) => "?:??".to_owned(),
+        }
+    }
+}
+
+/// How long before the end of a track the one after it is made ready
+const PREFETCH_LEAD: Duration = Duration::from_secs(10);
+
+/// Add a track to the back of the queue and store its metadata in the typemap
+pub async fn enqueue(call: &mut Call, input: Input, info: TrackInfo, volume: f32) -> TrackHandle {
+    // Songbird's own preloading runs yt-dlp for every track to find out how long it is, so it is
+    // turned off and PrefetchNext readies the next track near the end of this one instead
+    let handle = call.enqueue_with_preload(Track::from(input).volume(volume), None);
+    let mut typemap = handle.typemap().write().await;
+    typemap.insert::<SongTitleKey>(info.title);
+    typemap.insert::<SongUrlKey>(info.url);
+    if let Some(duration) = info.duration {
+        typemap.insert::<SongDurationKey>(duration);
+    }
+    if let Some(requester) = info.requester {
+        typemap.insert::<SongRequesterKey>(requester);
+    }
+    if let Some(live) = info.live {
+        typemap.insert::<SongLiveKey>(live);
+    }
+    drop(typemap);
+
+    // The track playing now may already be past the point where it gets the next one ready
+    let queue = call.queue().current_queue();
+    if queue.len() == 2 {
+        reschedule_prefetch(&queue[0]).await;
+    }
+    handle
+}
+
+/// Lets a track get the one after it ready shortly before it ends, kept in its typemap
+pub struct Prefetch {
+    /// The call the track is queued in, only weakly held since the queue of the call holds the
+    /// track and with it this
+    call: Weak<Mutex<Call>>,
+    /// Waits until it is time to prefetch, replaced whenever that time moves
+    timer: Option<AbortHandle>,
+}
+
+/// Get the track after this one ready once this one is about to end, counting from `position`
+/// and replacing whatever was scheduled for it before
+pub async fn schedule_prefetch(handle: &TrackHandle, position: Duration, call: Weak<Mutex<Call>>) {
+    let mut typemap = handle.typemap().write().await;
+    // There is no telling when a track of unknown length ends, so the next one is readied now
+    let wait = typemap
+        .get::<SongDurationKey>()
+        .map_or(Duration::ZERO, |duration| {
+            duration
+                .saturating_sub(position)
+                .saturating_sub(PREFETCH_LEAD)
+        });
+    let (timer_call, current) = (call.clone(), handle.clone());
+    let timer = tokio::spawn(async move {
+        tokio::time::sleep(wait).await;
+        prefetch_after(&timer_call, &current).await;
+    });
+    let timer = Some(timer.abort_handle());
+    match typemap.get_mut::<PrefetchKey>() {
+        Some(prefetch) => {
+            if let Some(earlier) = std::mem::replace(&mut prefetch.timer, timer) {
+                earlier.abort();
+            }
+        }
+        None => typemap.insert::<PrefetchKey>(Prefetch { call, timer }),
+    }
+}
+
+/// Drop what was scheduled for a track that paused or ended
+pub async fn cancel_prefetch(handle: &TrackHandle) {
+    if let Some(prefetch) = handle.typemap().write().await.get_mut::<PrefetchKey>() {
+        if let Some(timer) = prefetch.timer.take() {
+            timer.abort();
+        }
+    }
+}
+
+/// Schedule the prefetch of a playing track again after its end moved closer or further away
+async fn reschedule_prefetch(handle: &TrackHandle) {
+    // Tracks that never started playing have nothing scheduled yet
+    let Some(call) = handle
+        .typemap()
+        .read()
+        .await
+        .get::<PrefetchKey>()
+        .map(|prefetch| prefetch.call.clone())
+    else {
+        return;
+    };
+    if let Ok(state) = handle.get_info().await {
+        if state.playing == PlayMode::Play {
+            schedule_prefetch(handle, state.position, call).await;
+        }
+    }
+}
+
+/// Start a queued track at `position` instead of the beginning once it gets to play
+pub fn start_at(handle: &TrackHandle, position: Duration) {
+    if let Err(e) = handle.add_event(TrackEvent::Play.into(), SeekOnPlay { position }) {
+        tracing::warn!(err = %e, "Failed to schedule seeking to where the track should start.");
+    }
+}
+
+/// Seek to a position in a track and move its prefetch along with it
+pub async fn seek(handle: &TrackHandle, position: Duration) -> Result<Duration, ControlError> {
+    let position = handle.seek_async(position).await?;
+    reschedule_prefetch(handle).await;
+    Ok(position)
+}
+
+async fn prefetch_after(call: &Weak<Mutex<Call>>, handle: &TrackHandle) {
+    // Gone once the bot left the voice channel, and the queue along with it
+    let Some(call_lock) = call.upgrade() else {
+        return;
+    };
+    let tracks = call_lock.lock().await.queue().current_queue();
+    let Some(next) = tracks
+        .iter()
+        .position(|queued| queued.uuid() == handle.uuid())
+        .and_then(|i| tracks.get(i + 1))
+        .cloned()
+    else {
+        return;
+    };
+    let info = TrackInfo::from_handle(&next).await;
+    let span = tracing::info_span!("prefetch", url = %info.url);
+    async move {
+        let start = Instant::now();
+        match next.make_playable().result_async().await {
+            Ok(()) => tracing::info!(elapsed = ?start.elapsed(), "Next track is ready."),
+            Err(e) => tracing::warn!(err = %e, "Failed to get the next track ready."),
+        }
+    }
+    .instrument(span)
+    .await;
+}
+
+/// Add a new copy of a track to the back of the queue, used when looping the queue
+pub async fn requeue(call: &mut Call, resolvers: &Resolvers, handle: &TrackHandle, volume: f32) {
+    let info = TrackInfo::from_handle(handle).await;
+    let Some(input) = resolvers.replay(&info) else {
+        tracing::warn!(
+            "Don't know how to play \"{}\" again, dropping it.",
+            info.url
+        );
+        return;
+    };
+    enqueue(call, input, info, volume).await;
+}
+
+/// How often the position of the current track is saved, so a restart loses at most this much
+const QUEUE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Join a voice channel, or move there if already in another channel of the guild, and set up
+/// the event handlers the queue relies on
+pub async fn connect(
+    manager: &Arc<Songbird>,
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> JoinResult<Arc<Mutex<Call>>> {
+    let call_lock = manager.join(guild_id, channel_id).await?;
+    let mut call = call_lock.lock().await;
+    call.remove_all_global_events();
+    // Attach an event handler to see notifications of all track errors.
+    call.add_global_event(TrackEvent::Error.into(), TrackErrorNotifier);
+    for event in [TrackEvent::Play, TrackEvent::End] {
+        let loop_handler = LoopHandler {
+            guild_id,
+            guild_states: data.guild_states.clone(),
+            manager: manager.clone(),
+            resolvers: data.resolvers.clone(),
+        };
+        call.add_global_event(event.into(), loop_handler);
+    }
+    for event in [TrackEvent::Play, TrackEvent::Pause, TrackEvent::End] {
+        let prefetch = PrefetchNext {
+            call: Arc::downgrade(&call_lock),
+        };
+        call.add_global_event(event.into(), prefetch);
+    }
+    for event in [
+        Event::Track(TrackEvent::Play),
+        Event::Track(TrackEvent::End),
+        Event::Periodic(QUEUE_SAVE_INTERVAL, None),
+    ] {
+        let saver = QueueSaver {
+            guild_id,
+            guild_states: data.guild_states.clone(),
+            manager: manager.clone(),
+            saved_queues: data.saved_queues.clone(),
+        };
+        call.add_global_event(event, saver);
+    }
+    drop(call);
+    Ok(call_lock)
+}
+
+/// Leave the voice channel of a guild and let go of its queue, which stays around otherwise
+pub async fn disconnect(manager: &Songbird, data: &Data, guild_id: GuildId) -> JoinResult<()> {
+    data.guild_states
+        .update(guild_id, |state| state.following = None);
+    // Stopped first, since the driver keeps the queued tracks around even after the call is gone
+    if let Some(call) = manager.get(guild_id) {
+        call.lock().await.queue().stop();
+    }
+    let result = manager.remove(guild_id).await;
+    // Forgotten only once the call is gone, so no save can write the queue back
+    saved_queue::forget(&data.saved_queues, guild_id);
+    result
+}
@@ -0,0 +1,255 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use serenity::all::UserId;
+
+use crate::{storage::Storage, timestamp::parse_timestamp};
+
+pub type Podcasts = Storage<HashMap<UserId, Listener>>;
+
+pub const PODCASTS_PATH: &str = "./data/podcasts.json";
+
+/// How long a feed gets to download before giving up on it
+const FEED_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Largest feed that is downloaded, feeds of long running podcasts with show notes get big but
+/// not bigger than this
+const MAX_FEED_SIZE: usize = 20 * 1024 * 1024;
+
+/// Where a user got to in the episodes they have listened to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Listener {
+    /// Enclosure url of the episode they played last
+    #[serde(default)]
+    pub last: Option<String>,
+    /// Episodes that were stopped before the end, by enclosure url
+    #[serde(default)]
+    pub episodes: HashMap<String, Progress>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub title: String,
+    pub position: Duration,
+    pub duration: Option<Duration>,
+}
+
+/// A podcast episode with the url of its audio file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Episode {
+    pub title: String,
+    pub url: String,
+    pub published: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Feed {
+    pub title: Option<String>,
+    /// Episodes in the order of the feed, usually newest first
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedError {
+    Request(String),
+    TooLarge,
+    Invalid(String),
+}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedError::Request(e) => write!(f, "failed to download the feed: {}", e),
+            FeedError::TooLarge => write!(
+                f,
+                "the feed is bigger than {} MB",
+                MAX_FEED_SIZE / 1024 / 1024
+            ),
+            FeedError::Invalid(e) => write!(f, "the feed isn't valid RSS or Atom: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FeedError {}
+
+/// Download and parse an RSS or Atom feed
+pub async fn fetch_feed(http_client: &HttpClient, url: &str) -> Result<Feed, FeedError> {
+    let request_error = |e: reqwest::Error| FeedError::Request(e.to_string());
+    let mut response = http_client
+        .get(url)
+        .timeout(FEED_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(request_error)?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_FEED_SIZE as u64)
+    {
+        return Err(FeedError::TooLarge);
+    }
+    // The length can be left out, so the size is checked while downloading as well
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+        if body.len() + chunk.len() > MAX_FEED_SIZE {
+            return Err(FeedError::TooLarge);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    parse_feed(&String::from_utf8_lossy(&body))
+}
+
+/// The url of the audio file, from an RSS `<enclosure>` or an Atom `<link rel="enclosure">`
+fn enclosure_url(tag: &BytesStart) -> Option<String> {
+    let attribute = |name: &str| {
+        tag.try_get_attribute(name)
+            .ok()
+            .flatten()
+            .and_then(|a| a.unescape_value().ok())
+            .map(|value| value.into_owned())
+    };
+    match tag.local_name().as_ref() {
+        b"enclosure" => attribute("url"),
+        b"link" if attribute("rel").as_deref() == Some("enclosure") => attribute("href"),
+        _ => None,
+    }
+}
+
+/// Parse the episodes with audio out of an RSS or Atom feed, ignoring the ones without it
+pub fn parse_feed(xml: &str) -> Result<Feed, FeedError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut feed = Feed::default();
+    let mut episode: Option<Episode> = None;
+    // The element the text we get belongs to
+    let mut current = Vec::new();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| FeedError::Invalid(e.to_string()))?;
+        let text = match event {
+            Event::Start(tag) => {
+                let name = tag.local_name().as_ref().to_vec();
+                if matches!(name.as_slice(), b"item" | b"entry") {
+                    episode = Some(Episode::default());
+                }
+                if let (Some(episode), Some(url)) = (&mut episode, enclosure_url(&tag)) {
+                    episode.url = url;
+                }
+                current = name;
+                continue;
+            }
+            Event::Empty(tag) => {
+                if let (Some(episode), Some(url)) = (&mut episode, enclosure_url(&tag)) {
+                    episode.url = url;
+                }
+                continue;
+            }
+            Event::End(tag) => {
+                if matches!(tag.local_name().as_ref(), b"item" | b"entry") {
+                    if let Some(episode) = episode.take().filter(|e| !e.url.is_empty()) {
+                        feed.episodes.push(episode);
+                    }
+                }
+                current.clear();
+                continue;
+            }
+            Event::Text(text) => text
+                .unescape()
+                .map_err(|e| FeedError::Invalid(e.to_string()))?
+                .into_owned(),
+            Event::CData(data) => String::from_utf8_lossy(&data).into_owned(),
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        match (&mut episode, current.as_slice()) {
+            (None, b"title") if feed.title.is_none() => feed.title = Some(text),
+            (Some(episode), b"title") if episode.title.is_empty() => episode.title = text,
+            (Some(episode), b"pubDate" | b"published" | b"updated") => {
+                episode.published.get_or_insert(text);
+            }
+            (Some(episode), b"duration") => episode.duration = parse_timestamp(&text).ok(),
+            _ => {}
+        }
+    }
+    Ok(feed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rss() {
+        let xml = r#"<?xml version="1.0"?>
+            <rss xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+              <channel>
+                <title>Talk &amp; Music</title>
+                <item>
+                  <title><![CDATA[Episode 2: Second]]></title>
+                  <pubDate>Tue, 02 Jan 2024 10:00:00 GMT</pubDate>
+                  <enclosure url="https://example.com/2.mp3" type="audio/mpeg" length="1"/>
+                  <itunes:duration>1:02:03</itunes:duration>
+                </item>
+                <item>
+                  <title>Text only</title>
+                </item>
+                <item>
+                  <title>Episode 1</title>
+                  <enclosure url="https://example.com/1.mp3?a=1&amp;b=2"/>
+                  <itunes:duration>600</itunes:duration>
+                </item>
+              </channel>
+            </rss>"#;
+        let feed = parse_feed(xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Talk & Music"));
+        assert_eq!(
+            feed.episodes,
+            vec![
+                Episode {
+                    title: "Episode 2: Second".to_owned(),
+                    url: "https://example.com/2.mp3".to_owned(),
+                    published: Some("Tue, 02 Jan 2024 10:00:00 GMT".to_owned()),
+                    duration: Some(Duration::from_secs(3723)),
+                },
+                Episode {
+                    title: "Episode 1".to_owned(),
+                    url: "https://example.com/1.mp3?a=1&b=2".to_owned(),
+                    published: None,
+                    duration: Some(Duration::from_secs(600)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <title>Atom cast</title>
+              <entry>
+                <title>First</title>
+                <link rel="alternate" href="https://example.com/first"/>
+                <link rel="enclosure" type="audio/mpeg" href="https://example.com/first.mp3"/>
+                <updated>2024-01-01T00:00:00Z</updated>
+              </entry>
+            </feed>"#;
+        let feed = parse_feed(xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Atom cast"));
+        assert_eq!(feed.episodes.len(), 1);
+        assert_eq!(feed.episodes[0].url, "https://example.com/first.mp3");
+        assert_eq!(
+            feed.episodes[0].published.as_deref(),
+            Some("2024-01-01T00:00:00Z")
+        );
+
+        assert!(parse_feed("<rss><channel></item></rss>").is_err());
+    }
+}
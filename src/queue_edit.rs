@@ -0,0 +1,146 @@
+//! Edits to the queue using the 1-based positions shown by the queue command, where position 1
+//! is the currently playing song and can never be moved.
+
+use std::{collections::VecDeque, fmt};
+
+use rand::{seq::SliceRandom, Rng};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueEditError {
+    CurrentTrack,
+    OutOfRange { position: usize, len: usize },
+}
+
+impl fmt::Display for QueueEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueEditError::CurrentTrack => {
+                write!(
+                    f,
+                    "Position 1 is the song that is playing right now, use skip instead."
+                )
+            }
+            QueueEditError::OutOfRange { position, len } => {
+                write!(
+                    f,
+                    "There is no song at position {}, the queue only has {} songs.",
+                    position, len
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueueEditError {}
+
+/// Turn a position from the queue command into an index, refusing the current track
+fn index<T>(queue: &VecDeque<T>, position: usize) -> Result<usize, QueueEditError> {
+    if position == 1 {
+        return Err(QueueEditError::CurrentTrack);
+    }
+    if position == 0 || position > queue.len() {
+        return Err(QueueEditError::OutOfRange {
+            position,
+            len: queue.len(),
+        });
+    }
+    Ok(position - 1)
+}
+
+pub fn remove<T>(queue: &mut VecDeque<T>, position: usize) -> Result<T, QueueEditError> {
+    let i = index(queue, position)?;
+    Ok(queue.remove(i).expect("Index checked above."))
+}
+
+pub fn move_to<T>(queue: &mut VecDeque<T>, from: usize, to: usize) -> Result<(), QueueEditError> {
+    let (from, to) = (index(queue, from)?, index(queue, to)?);
+    let item = queue.remove(from).expect("Index checked above.");
+    queue.insert(to, item);
+    Ok(())
+}
+
+pub fn swap<T>(queue: &mut VecDeque<T>, a: usize, b: usize) -> Result<(), QueueEditError> {
+    let (a, b) = (index(queue, a)?, index(queue, b)?);
+    queue.swap(a, b);
+    Ok(())
+}
+
+/// Remove everything except the current track, returning the removed tracks
+pub fn clear<T>(queue: &mut VecDeque<T>) -> Vec<T> {
+    if queue.is_empty() {
+        return vec![];
+    }
+    queue.drain(1..).collect()
+}
+
+/// Shuffle everything except the current track
+pub fn shuffle<T>(queue: &mut VecDeque<T>, rng: &mut impl Rng) {
+    if queue.len() > 2 {
+        queue.make_contiguous()[1..].shuffle(rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn queue() -> VecDeque<u32> {
+        VecDeque::from([1, 2, 3, 4, 5])
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut q = queue();
+        assert_eq!(remove(&mut q, 3), Ok(3));
+        assert_eq!(q, [1, 2, 4, 5]);
+        assert_eq!(remove(&mut q, 1), Err(QueueEditError::CurrentTrack));
+        assert_eq!(
+            remove(&mut q, 5),
+            Err(QueueEditError::OutOfRange {
+                position: 5,
+                len: 4
+            })
+        );
+        assert_eq!(
+            remove(&mut q, 0),
+            Err(QueueEditError::OutOfRange {
+                position: 0,
+                len: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_move_and_swap() {
+        let mut q = queue();
+        move_to(&mut q, 5, 2).unwrap();
+        assert_eq!(q, [1, 5, 2, 3, 4]);
+        move_to(&mut q, 2, 5).unwrap();
+        assert_eq!(q, [1, 2, 3, 4, 5]);
+        assert_eq!(move_to(&mut q, 3, 1), Err(QueueEditError::CurrentTrack));
+
+        swap(&mut q, 2, 4).unwrap();
+        assert_eq!(q, [1, 4, 3, 2, 5]);
+        assert_eq!(swap(&mut q, 1, 4), Err(QueueEditError::CurrentTrack));
+        assert_eq!(q, [1, 4, 3, 2, 5]);
+    }
+
+    #[test]
+    fn test_clear_and_shuffle() {
+        let mut q = queue();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..10 {
+            shuffle(&mut q, &mut rng);
+            assert_eq!(q[0], 1);
+        }
+        let mut sorted = q.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+        assert_eq!(sorted, [1, 2, 3, 4, 5]);
+
+        assert_eq!(clear(&mut q).len(), 4);
+        assert_eq!(q, [1]);
+        assert!(clear(&mut VecDeque::<u32>::new()).is_empty());
+    }
+}
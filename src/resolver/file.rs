@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::Url;
+use serenity::async_trait;
+use songbird::input::{File, Input};
+
+use super::{PlayError, Resolved, TrackResolver};
+use crate::player::TrackInfo;
+
+/// Plays files below a directory on the machine the bot runs on, given as `file://` urls
+#[derive(Debug, Clone)]
+pub struct FileResolver {
+    root: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new(root: impl Into<PathBuf>) -> FileResolver {
+        FileResolver { root: root.into() }
+    }
+
+    /// Get the path of the file, making sure it doesn't point outside of the root
+    async fn path(&self, query: &str) -> Result<PathBuf, PlayError> {
+        let unsupported = || PlayError::UnsupportedUrl(query.to_owned());
+        let path = Url::parse(query)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(unsupported)?;
+        let root = tokio::fs::canonicalize(&self.root)
+            .await
+            .map_err(|_| PlayError::NoResults(query.to_owned()))?;
+        let path = tokio::fs::canonicalize(&path)
+            .await
+            .map_err(|_| PlayError::NoResults(query.to_owned()))?;
+        if !path.starts_with(&root) || !path.is_file() {
+            return Err(unsupported());
+        }
+        Ok(path)
+    }
+}
+
+/// Name of the file without the extension, for when it has no title tag
+pub fn file_title(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Unknown".to_owned())
+}
+
+#[async_trait]
+impl TrackResolver for FileResolver {
+    fn handles(&self, query: &str) -> bool {
+        query.starts_with("file://")
+    }
+
+    async fn resolve(&self, query: &str, _max_tracks: usize) -> Result<Resolved, PlayError> {
+        let path = self.path(query).await?;
+        let info = TrackInfo {
+            title: file_title(&path),
+            url: Url::from_file_path(&path)
+                .map(String::from)
+                .unwrap_or_else(|_| query.to_owned()),
+            duration: None,
+            requester: None,
+            live: None,
+        };
+        Ok(Resolved::single(info, File::new(path).into()))
+    }
+
+    fn replay(&self, info: &TrackInfo) -> Input {
+        let path = Url::parse(&info.url)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .unwrap_or_else(|| PathBuf::from(&info.url));
+        File::new(path).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stays_inside_root() {
+        let dir = std::env::temp_dir().join(format!("music_bot_files_{}", std::process::id()));
+        let root = dir.join("music");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("song.mp3"), b"").unwrap();
+        fs::write(dir.join("secret.mp3"), b"").unwrap();
+        let resolver = FileResolver::new(&root);
+        let url = |path: PathBuf| Url::from_file_path(path).unwrap().to_string();
+
+        let resolved = resolver
+            .resolve(&url(root.join("song.mp3")), 1)
+            .await
+            .unwrap();
+        assert_eq!(resolved.tracks[0].info.title, "song");
+
+        let outside = resolver
+            .resolve(&url(root.join("..").join("secret.mp3")), 1)
+            .await;
+        assert!(matches!(outside, Err(PlayError::UnsupportedUrl(_))));
+        let missing = resolver.resolve(&url(root.join("nope.mp3")), 1).await;
+        assert!(matches!(missing, Err(PlayError::NoResults(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use percent_encoding::percent_decode_str;
+use reqwest::{Client as HttpClient, Url};
+use serenity::async_trait;
+use songbird::input::{HttpRequest, Input};
+
+use super::{probe, PlayError, Resolved, TrackResolver, AUDIO_EXTENSIONS};
+use crate::player::TrackInfo;
+
+/// Plays links that point directly at audio files
+#[derive(Debug, Clone)]
+pub struct HttpResolver {
+    http_client: HttpClient,
+}
+
+impl HttpResolver {
+    pub fn new(http_client: HttpClient) -> HttpResolver {
+        HttpResolver { http_client }
+    }
+}
+
+/// The name of the file a url points to, if it is an audio file we can play directly
+pub(super) fn audio_file_name(url: &Url) -> Option<String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let name = url.path_segments()?.next_back()?;
+    let (stem, extension) = name.rsplit_once('.')?;
+    if !AUDIO_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+        return None;
+    }
+    Some(percent_decode_str(stem).decode_utf8_lossy().into_owned())
+}
+
+#[async_trait]
+impl TrackResolver for HttpResolver {
+    fn handles(&self, query: &str) -> bool {
+        Url::parse(query)
+            .ok()
+            .and_then(|url| audio_file_name(&url))
+            .is_some()
+    }
+
+    async fn resolve(&self, query: &str, _max_tracks: usize) -> Result<Resolved, PlayError> {
+        let url = Url::parse(query).map_err(|_| PlayError::UnsupportedUrl(query.to_owned()))?;
+        let file_name =
+            audio_file_name(&url).ok_or_else(|| PlayError::UnsupportedUrl(query.to_owned()))?;
+        let mut info = TrackInfo {
+            title: file_name,
+            url: query.to_owned(),
+            duration: None,
+            requester: None,
+            live: None,
+        };
+
+        // Read the tags from a separate request, a stream opened now might time out before
+        // the track gets to play if the queue is long.
+        let tags = probe(self.replay(&info)).await?;
+        if let Some(title) = tags.display_title() {
+            info.title = title;
+        }
+        info.duration = tags.duration;
+        let input = self.replay(&info);
+        Ok(Resolved::single(info, input))
+    }
+
+    fn replay(&self, info: &TrackInfo) -> Input {
+        HttpRequest::new(self.http_client.clone(), info.url.clone()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handles() {
+        let resolver = HttpResolver::new(HttpClient::new());
+        assert!(resolver.handles("https://example.com/music/My%20Song.mp3"));
+        assert!(resolver.handles("http://example.com/a.FLAC?token=abc"));
+        assert!(!resolver.handles("https://example.com/page.html"));
+        assert!(!resolver.handles("https://www.youtube.com/watch?v=abc"));
+        assert!(!resolver.handles("ftp://example.com/a.mp3"));
+        assert!(!resolver.handles("song.mp3"));
+
+        let url = Url::parse("https://example.com/music/My%20Song.mp3").unwrap();
+        assert_eq!(audio_file_name(&url).as_deref(), Some("My Song"));
+    }
+}
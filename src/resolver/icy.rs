@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+/// What a live stream says it is playing right now, shared with the task reading the stream
+#[derive(Debug, Clone, Default)]
+pub struct StreamTitle(Arc<Mutex<Option<String>>>);
+
+impl StreamTitle {
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().clone()
+    }
+
+    pub fn set(&self, title: String) {
+        *self.0.lock() = Some(title);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Audio bytes left before the next metadata block
+    Audio(usize),
+    /// The next byte is the length of the metadata block
+    Length,
+    /// Reading a metadata block of `len` bytes
+    Metadata { len: usize, data: Vec<u8> },
+}
+
+/// Splits the metadata blocks Icecast and Shoutcast put between the audio every `icy-metaint`
+/// bytes out of the stream
+#[derive(Debug, Clone)]
+pub struct IcyParser {
+    metaint: usize,
+    state: State,
+}
+
+impl IcyParser {
+    pub fn new(metaint: usize) -> IcyParser {
+        IcyParser {
+            metaint,
+            state: State::Audio(metaint),
+        }
+    }
+
+    /// Add the audio in the chunk to `audio`, returning the last new title found in it
+    pub fn feed(&mut self, mut chunk: &[u8], audio: &mut Vec<u8>) -> Option<String> {
+        let mut title = None;
+        while !chunk.is_empty() {
+            match &mut self.state {
+                State::Audio(left) => {
+                    let n = (*left).min(chunk.len());
+                    audio.extend_from_slice(&chunk[..n]);
+                    chunk = &chunk[n..];
+                    *left -= n;
+                    if *left == 0 {
+                        self.state = State::Length;
+                    }
+                }
+                State::Length => {
+                    let len = usize::from(chunk[0]) * 16;
+                    chunk = &chunk[1..];
+                    self.state = match len {
+                        0 => State::Audio(self.metaint),
+                        len => State::Metadata {
+                            len,
+                            data: Vec::with_capacity(len),
+                        },
+                    };
+                }
+                State::Metadata { len, data } => {
+                    let n = (*len - data.len()).min(chunk.len());
+                    data.extend_from_slice(&chunk[..n]);
+                    chunk = &chunk[n..];
+                    if data.len() == *len {
+                        title = parse_stream_title(data).or(title);
+                        self.state = State::Audio(self.metaint);
+                    }
+                }
+            }
+        }
+        title
+    }
+}
+
+/// Get the title out of a metadata block like `StreamTitle='Artist - Song';StreamUrl='';`
+fn parse_stream_title(data: &[u8]) -> Option<String> {
+    const START: &str = "StreamTitle='";
+    let text = String::from_utf8_lossy(data);
+    let text = text.trim_end_matches('\0');
+    let start = text.find(START)? + START.len();
+    let end = text[start..]
+        .find("';")
+        .map(|end| start + end)
+        .unwrap_or_else(|| text.trim_end_matches(['\'', ';']).len().max(start));
+    let title = text[start..end].trim();
+    (!title.is_empty()).then(|| title.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(text: &str) -> Vec<u8> {
+        let mut block = text.as_bytes().to_vec();
+        block.resize(text.len().div_ceil(16) * 16, 0);
+        let mut out = vec![(block.len() / 16) as u8];
+        out.extend(block);
+        out
+    }
+
+    #[test]
+    fn test_parse_stream_title() {
+        assert_eq!(
+            parse_stream_title(b"StreamTitle='Artist - Song';StreamUrl='';\0\0"),
+            Some("Artist - Song".to_owned())
+        );
+        assert_eq!(
+            parse_stream_title(b"StreamTitle='It's here';"),
+            Some("It's here".to_owned())
+        );
+        assert_eq!(parse_stream_title(b"StreamTitle='';"), None);
+        assert_eq!(parse_stream_title(b"StreamUrl='x';"), None);
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut stream = b"abcd".to_vec();
+        stream.extend(metadata("StreamTitle='First';"));
+        stream.extend(b"efgh");
+        stream.push(0);
+        stream.extend(b"ijkl");
+        stream.extend(metadata("StreamTitle='Second';"));
+        stream.extend(b"mn");
+
+        // Feeding it all at once and one byte at a time should give the same result
+        let mut parser = IcyParser::new(4);
+        let mut audio = Vec::new();
+        assert_eq!(parser.feed(&stream, &mut audio), Some("Second".to_owned()));
+        assert_eq!(audio, b"abcdefghijklmn");
+
+        let mut parser = IcyParser::new(4);
+        let mut audio = Vec::new();
+        let titles = stream
+            .chunks(1)
+            .filter_map(|byte| parser.feed(byte, &mut audio))
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["First", "Second"]);
+        assert_eq!(audio, b"abcdefghijklmn");
+    }
+}
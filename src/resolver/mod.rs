@@ -0,0 +1,323 @@
+use std::{fmt, sync::Arc, time::Instant};
+
+use serenity::async_trait;
+use songbird::input::{AudioStreamError, Input};
+
+use crate::player::TrackInfo;
+
+mod file;
+mod http;
+mod icy;
+mod radio;
+mod spotify;
+mod tags;
+mod youtube;
+
+pub use file::{file_title, FileResolver};
+pub use http::HttpResolver;
+pub use icy::StreamTitle;
+pub use radio::RadioResolver;
+pub use spotify::SpotifyTrackResolver;
+pub use tags::{probe, read_file_tags, AudioTags};
+pub use youtube::{search, YoutubeResolver};
+
+/// Extensions of the audio files we play directly instead of going through yt-dlp
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "m4a", "mp4", "aac", "flac", "ogg", "oga", "opus", "wav",
+];
+
+/// Reasons a song could not be added to the queue, with messages meant for the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayError {
+    NoResults(String),
+    ExtractorFailed(String),
+    YtDlpMissing,
+    UnsupportedUrl(String),
+    /// The source is known, but the bot isn't set up to play from it
+    SourceDisabled(&'static str),
+}
+
+impl PlayError {
+    /// Figure out what went wrong from the error yt-dlp gave when looking up `query`
+    pub fn from_stream_error(query: &str, err: AudioStreamError) -> PlayError {
+        PlayError::from_message(query, err.to_string())
+    }
+
+    fn from_message(query: &str, msg: String) -> PlayError {
+        if msg.contains("could not find executable") {
+            PlayError::YtDlpMissing
+        } else if msg.contains("no results found") {
+            PlayError::NoResults(query.to_owned())
+        } else if msg.contains("Unsupported URL") {
+            PlayError::UnsupportedUrl(query.to_owned())
+        } else {
+            PlayError::ExtractorFailed(msg)
+        }
+    }
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::NoResults(query) => write!(f, "Couldn't find anything for \"{}\".", query),
+            PlayError::ExtractorFailed(_) => write!(
+                f,
+                "Failed to get information about the song, try again or use a different link."
+            ),
+            PlayError::YtDlpMissing => write!(
+                f,
+                "yt-dlp isn't installed where the bot is running, so it can't play anything."
+            ),
+            PlayError::UnsupportedUrl(url) => write!(f, "Don't know how to play \"{}\".", url),
+            PlayError::SourceDisabled(source) => {
+                write!(f, "Playing {} isn't set up on this bot.", source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlayError {}
+
+/// A track that is ready to be added to the queue
+pub struct ResolvedTrack {
+    pub info: TrackInfo,
+    pub input: Input,
+}
+
+/// Everything a query resolved to, with the name of the playlist or album if it was one
+pub struct Resolved {
+    pub name: Option<String>,
+    pub tracks: Vec<ResolvedTrack>,
+}
+
+impl Resolved {
+    pub fn single(info: TrackInfo, input: Input) -> Resolved {
+        Resolved {
+            name: None,
+            tracks: vec![ResolvedTrack { info, input }],
+        }
+    }
+}
+
+/// Turns what users ask to play into tracks, each implementation handles one kind of source
+#[async_trait]
+pub trait TrackResolver: fmt::Debug + Send + Sync {
+    /// Whether this resolver knows how to play the query, usually decided by the url
+    fn handles(&self, query: &str) -> bool;
+
+    /// Find up to `max_tracks` tracks for the query
+    async fn resolve(&self, query: &str, max_tracks: usize) -> Result<Resolved, PlayError>;
+
+    /// Look at a query `handles` said no to over the network, resolving it right away if it
+    /// turns out to be for this resolver so nothing has to be asked twice. Asked before the
+    /// resolvers after this one get a chance.
+    async fn detect(&self, _query: &str, _max_tracks: usize) -> Option<Resolved> {
+        None
+    }
+
+    /// Create a new input for a track this resolver found before, used to play it again
+    fn replay(&self, info: &TrackInfo) -> Input;
+
+    /// Whether this resolver is the one to play the track again, by default decided by its url
+    fn replays(&self, info: &TrackInfo) -> bool {
+        self.handles(&info.url)
+    }
+}
+
+/// Picks the right resolver for each query, asking them in the order they were added
+#[derive(Debug, Clone, Default)]
+pub struct Resolvers {
+    resolvers: Vec<Arc<dyn TrackResolver>>,
+}
+
+impl Resolvers {
+    pub fn new() -> Resolvers {
+        Resolvers::default()
+    }
+
+    pub fn with(mut self, resolver: impl TrackResolver + 'static) -> Self {
+        self.resolvers.push(Arc::new(resolver));
+        self
+    }
+
+    /// Resolve the query with the first resolver that handles or detects it
+    async fn resolve_with_first(
+        &self,
+        query: &str,
+        max_tracks: usize,
+    ) -> Result<Resolved, PlayError> {
+        for resolver in &self.resolvers {
+            if resolver.handles(query) {
+                return resolver.resolve(query, max_tracks).await;
+            }
+            if let Some(resolved) = resolver.detect(query, max_tracks).await {
+                return Ok(resolved);
+            }
+        }
+        Err(PlayError::UnsupportedUrl(query.to_owned()))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve(&self, query: &str, max_tracks: usize) -> Result<Resolved, PlayError> {
+        let start = Instant::now();
+        let resolved = self.resolve_with_first(query, max_tracks).await;
+        tracing::debug!(elapsed = ?start.elapsed(), "Resolved query.");
+        let resolved = resolved?;
+        if resolved.tracks.is_empty() {
+            return Err(PlayError::NoResults(query.to_owned()));
+        }
+        Ok(resolved)
+    }
+
+    /// Create a new input for a track that was resolved before, using the url it was stored with
+    pub fn replay(&self, info: &TrackInfo) -> Option<Input> {
+        self.resolvers
+            .iter()
+            .find(|r| r.replays(info))
+            .map(|r| r.replay(info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use songbird::input::File;
+
+    use super::*;
+
+    /// Stands in for a real source, handling queries that start with its prefix
+    #[derive(Debug)]
+    struct Fake {
+        prefix: &'static str,
+        tracks: usize,
+    }
+
+    #[async_trait]
+    impl TrackResolver for Fake {
+        fn handles(&self, query: &str) -> bool {
+            query.starts_with(self.prefix)
+        }
+
+        async fn resolve(&self, query: &str, max_tracks: usize) -> Result<Resolved, PlayError> {
+            let tracks = (0..self.tracks.min(max_tracks))
+                .map(|i| ResolvedTrack {
+                    info: TrackInfo {
+                        title: format!("{} {}", self.prefix, i),
+                        url: query.to_owned(),
+                        duration: None,
+                        requester: None,
+                        live: None,
+                    },
+                    input: File::new(PathBuf::from(query)).into(),
+                })
+                .collect();
+            Ok(Resolved {
+                name: Some(self.prefix.to_owned()),
+                tracks,
+            })
+        }
+
+        fn replay(&self, info: &TrackInfo) -> Input {
+            File::new(info.url.clone()).into()
+        }
+    }
+
+    /// Doesn't recognize any query by itself, but finds out the ones containing "live" are its own
+    #[derive(Debug)]
+    struct Detecting;
+
+    #[async_trait]
+    impl TrackResolver for Detecting {
+        fn handles(&self, _query: &str) -> bool {
+            false
+        }
+
+        async fn detect(&self, query: &str, max_tracks: usize) -> Option<Resolved> {
+            if !query.contains("live") {
+                return None;
+            }
+            self.resolve(query, max_tracks).await.ok()
+        }
+
+        async fn resolve(&self, query: &str, _max_tracks: usize) -> Result<Resolved, PlayError> {
+            let info = TrackInfo {
+                title: "Detected".to_owned(),
+                url: query.to_owned(),
+                duration: None,
+                requester: None,
+                live: Some(StreamTitle::default()),
+            };
+            Ok(Resolved::single(
+                info,
+                File::new(PathBuf::from(query)).into(),
+            ))
+        }
+
+        fn replay(&self, info: &TrackInfo) -> Input {
+            File::new(info.url.clone()).into()
+        }
+
+        fn replays(&self, info: &TrackInfo) -> bool {
+            info.live.is_some()
+        }
+    }
+
+    fn resolvers() -> Resolvers {
+        Resolvers::new()
+            .with(Detecting)
+            .with(Fake {
+                prefix: "one:",
+                tracks: 1,
+            })
+            .with(Fake {
+                prefix: "many:",
+                tracks: 5,
+            })
+            .with(Fake {
+                prefix: "none:",
+                tracks: 0,
+            })
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let resolvers = resolvers();
+
+        let one = resolvers.resolve("one:song", 10).await.unwrap();
+        assert_eq!(one.name.as_deref(), Some("one:"));
+        assert_eq!(one.tracks.len(), 1);
+
+        let many = resolvers.resolve("many:songs", 3).await.unwrap();
+        assert_eq!(many.name.as_deref(), Some("many:"));
+        assert_eq!(many.tracks.len(), 3);
+        assert_eq!(many.tracks[2].info.title, "many: 2");
+
+        let live = resolvers.resolve("one:live", 10).await.unwrap();
+        assert_eq!(live.tracks[0].info.title, "Detected");
+        assert!(resolvers.replay(&live.tracks[0].info).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_errors() {
+        let resolvers = resolvers();
+
+        assert_eq!(
+            resolvers.resolve("none:song", 10).await.err(),
+            Some(PlayError::NoResults("none:song".to_owned()))
+        );
+        assert_eq!(
+            resolvers.resolve("other:song", 10).await.err(),
+            Some(PlayError::UnsupportedUrl("other:song".to_owned()))
+        );
+
+        let info = TrackInfo {
+            title: "Song".to_owned(),
+            url: "other:song".to_owned(),
+            duration: None,
+            requester: None,
+            live: None,
+        };
+        assert!(resolvers.replay(&info).is_none());
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::HashMap,
+    io::{Error as IoError, ErrorKind, SeekFrom},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, SERVER},
+    Client as HttpClient, Url,
+};
+use serenity::async_trait;
+use songbird::input::{
+    core::{io::MediaSource, probe::Hint},
+    AsyncAdapterStream, AsyncMediaSource, AudioStream, AudioStreamError, Compose, HlsRequest,
+    Input,
+};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWriteExt, DuplexStream, ReadBuf};
+
+use super::{
+    http::audio_file_name, icy::IcyParser, PlayError, Resolved, StreamTitle, TrackResolver,
+};
+use crate::player::TrackInfo;
+
+/// Plays endless live streams: Icecast and Shoutcast radio, playlists pointing at them and HLS
+#[derive(Debug, Clone)]
+pub struct RadioResolver {
+    http_client: HttpClient,
+    /// Names of the configured stations by their url
+    stations: HashMap<String, String>,
+}
+
+impl RadioResolver {
+    /// The stations are by name, like in the config
+    pub fn new(http_client: HttpClient, stations: &HashMap<String, String>) -> RadioResolver {
+        RadioResolver {
+            http_client,
+            stations: stations
+                .iter()
+                .map(|(name, url)| (url.clone(), name.clone()))
+                .collect(),
+        }
+    }
+
+    /// Describe a station, named after the config, then the name the stream goes by in its
+    /// `headers` and then the host it is on
+    fn station_info(&self, query: &str, url: &Url, headers: Option<&HeaderMap>) -> TrackInfo {
+        let title = self
+            .stations
+            .get(query)
+            .cloned()
+            .or_else(|| headers.and_then(|headers| header(headers, "icy-name")))
+            .or_else(|| url.host_str().map(str::to_owned))
+            .unwrap_or_else(|| query.to_owned());
+        TrackInfo {
+            title,
+            url: query.to_owned(),
+            duration: None,
+            requester: None,
+            live: Some(StreamTitle::default()),
+        }
+    }
+}
+
+/// The kinds of links to live streams we know how to play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamKind {
+    /// An m3u or pls file listing the stream url
+    Playlist,
+    Hls,
+    /// Links straight to an Icecast or Shoutcast stream
+    Icecast,
+}
+
+fn stream_kind(url: &Url) -> StreamKind {
+    let extension = url
+        .path()
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase());
+    match extension.as_deref() {
+        Some("m3u" | "pls") => StreamKind::Playlist,
+        Some("m3u8") => StreamKind::Hls,
+        _ => StreamKind::Icecast,
+    }
+}
+
+fn is_http(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// Get the first stream from the text of an m3u or pls playlist
+fn parse_playlist(text: &str) -> Option<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| match line.split_once('=') {
+            // pls entries look like File1=http://...
+            Some((key, url)) if key.to_lowercase().starts_with("file") => url.trim(),
+            _ => line,
+        })
+        .find(|line| Url::parse(line).is_ok_and(|url| is_http(&url)))
+        .map(str::to_owned)
+}
+
+/// Find the stream behind a link, downloading the playlist if it is one
+async fn stream_url(http_client: &HttpClient, url: &str) -> Result<String, String> {
+    let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+    if stream_kind(&parsed) != StreamKind::Playlist {
+        return Ok(url.to_owned());
+    }
+    let text = http_client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    parse_playlist(&text).ok_or_else(|| "the playlist doesn't contain any streams".to_owned())
+}
+
+/// Ask for the stream with ICY metadata, so we get the titles of the songs it plays
+async fn connect(http_client: &HttpClient, url: &str) -> Result<reqwest::Response, String> {
+    http_client
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())
+}
+
+/// Sites that are played through yt-dlp and never serve radio, not worth a request to check
+const VIDEO_HOSTS: &[&str] = &[
+    "youtube.com",
+    "youtu.be",
+    "soundcloud.com",
+    "bandcamp.com",
+    "vimeo.com",
+    "twitch.tv",
+    "dailymotion.com",
+    "mixcloud.com",
+];
+
+/// Words the host names and paths of Icecast and Shoutcast mounts are usually made of
+const MOUNT_WORDS: &[&str] = &[
+    "stream",
+    "listen",
+    "live",
+    "radio",
+    "icecast",
+    "shoutcast",
+    "mp3",
+    "aac",
+    "aacp",
+    "ogg",
+    "opus",
+];
+
+/// Whether a link could be a mount on a streaming server, the only links worth asking if they
+/// are live: servers on a port of their own, Shoutcast's `/;` and names made of stream words
+fn looks_like_mount(url: &Url) -> bool {
+    if url.port().is_some() || url.path().ends_with(';') {
+        return true;
+    }
+    let host = url.host_str().unwrap_or_default();
+    host.split('.')
+        .chain(url.path().split(|c: char| !c.is_ascii_alphanumeric()))
+        .any(|word| MOUNT_WORDS.contains(&word.to_lowercase().as_str()))
+}
+
+/// How long to wait for a link to answer when checking if it is a live stream
+const DETECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether a response is the start of an endless stream. Icecast and Shoutcast send icy- or ice-
+/// headers or name themselves as the server, audio without a length can just as well be a file
+/// that is served as it is generated.
+fn is_live(headers: &HeaderMap) -> bool {
+    let has_icy = headers.keys().any(|name| {
+        let name = name.as_str();
+        name.starts_with("icy-") || name.starts_with("ice-")
+    });
+    let is_stream_server = header(headers, SERVER.as_str()).is_some_and(|server| {
+        let server = server.to_lowercase();
+        server.contains("icecast") || server.contains("shoutcast")
+    });
+    has_icy || is_stream_server
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+#[async_trait]
+impl TrackResolver for RadioResolver {
+    fn handles(&self, query: &str) -> bool {
+        let Ok(url) = Url::parse(query) else {
+            return false;
+        };
+        is_http(&url)
+            && (self.stations.contains_key(query) || stream_kind(&url) != StreamKind::Icecast)
+    }
+
+    /// Plain links to Icecast and Shoutcast streams look like any other link, so ask the ones
+    /// that could be mounts and check the headers. Links to audio files are left to
+    /// `HttpResolver` without asking.
+    async fn detect(&self, query: &str, _max_tracks: usize) -> Option<Resolved> {
+        let url = Url::parse(query).ok()?;
+        let is_video_site = url.host_str().is_some_and(|host| {
+            VIDEO_HOSTS
+                .iter()
+                .any(|site| host == *site || host.ends_with(&format!(".{}", site)))
+        });
+        if !is_http(&url)
+            || is_video_site
+            || audio_file_name(&url).is_some()
+            || !looks_like_mount(&url)
+        {
+            return None;
+        }
+        let response =
+            match tokio::time::timeout(DETECT_TIMEOUT, connect(&self.http_client, query)).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => {
+                    tracing::debug!(err = %e, "Failed to check if \"{}\" is a live stream.", query);
+                    return None;
+                }
+                Err(_) => return None,
+            };
+        if !is_live(response.headers()) {
+            return None;
+        }
+        let info = self.station_info(query, &url, Some(response.headers()));
+        let input = self.replay(&info);
+        Some(Resolved::single(info, input))
+    }
+
+    async fn resolve(&self, query: &str, _max_tracks: usize) -> Result<Resolved, PlayError> {
+        let url = Url::parse(query).map_err(|_| PlayError::UnsupportedUrl(query.to_owned()))?;
+
+        // Connect once up front to catch dead stations and get the name they go by
+        let info = if stream_kind(&url) != StreamKind::Hls {
+            let stream = stream_url(&self.http_client, query)
+                .await
+                .map_err(PlayError::ExtractorFailed)?;
+            let response = connect(&self.http_client, &stream)
+                .await
+                .map_err(PlayError::ExtractorFailed)?;
+            self.station_info(query, &url, Some(response.headers()))
+        } else {
+            self.station_info(query, &url, None)
+        };
+
+        let input = self.replay(&info);
+        Ok(Resolved::single(info, input))
+    }
+
+    fn replay(&self, info: &TrackInfo) -> Input {
+        let is_hls = Url::parse(&info.url).is_ok_and(|url| stream_kind(&url) == StreamKind::Hls);
+        if is_hls {
+            return HlsRequest::new(self.http_client.clone(), info.url.clone()).into();
+        }
+        Input::Lazy(Box::new(IcyStream {
+            http_client: self.http_client.clone(),
+            url: info.url.clone(),
+            title: info.live.clone().unwrap_or_default(),
+        }))
+    }
+
+    fn replays(&self, info: &TrackInfo) -> bool {
+        let is_live_http =
+            info.live.is_some() && Url::parse(&info.url).is_ok_and(|url| is_http(&url));
+        is_live_http || self.handles(&info.url)
+    }
+}
+
+/// Songbird input for Icecast and Shoutcast streams that keeps the stream title up to date
+struct IcyStream {
+    http_client: HttpClient,
+    url: String,
+    title: StreamTitle,
+}
+
+fn stream_error(msg: String) -> AudioStreamError {
+    AudioStreamError::Fail(msg.into())
+}
+
+#[async_trait]
+impl Compose for IcyStream {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let stream = stream_url(&self.http_client, &self.url)
+            .await
+            .map_err(stream_error)?;
+        let mut response = connect(&self.http_client, &stream)
+            .await
+            .map_err(stream_error)?;
+        let headers = response.headers();
+        let metaint = header(headers, "icy-metaint").and_then(|value| value.parse().ok());
+        let hint = header(headers, CONTENT_TYPE.as_str()).map(|content_type| {
+            let mut hint = Hint::new();
+            hint.mime_type(&content_type);
+            hint
+        });
+
+        // A task takes the metadata out of the stream and passes the audio on to songbird,
+        // it stops when songbird drops its end of the pipe.
+        let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+        let title = self.title.clone();
+        tokio::spawn(async move {
+            let mut parser = metaint.filter(|&n| n > 0).map(IcyParser::new);
+            let mut audio = Vec::new();
+            while let Ok(Some(chunk)) = response.chunk().await {
+                audio.clear();
+                match &mut parser {
+                    Some(parser) => {
+                        if let Some(now_playing) = parser.feed(&chunk, &mut audio) {
+                            title.set(now_playing);
+                        }
+                    }
+                    None => audio.extend_from_slice(&chunk),
+                }
+                if writer.write_all(&audio).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let input = AsyncAdapterStream::new(Box::new(LiveSource(reader)), 64 * 1024);
+        Ok(AudioStream {
+            input: Box::new(input),
+            hint,
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+}
+
+/// The reading end of the pipe, which can't seek since the stream never ends
+struct LiveSource(DuplexStream);
+
+impl AsyncRead for LiveSource {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for LiveSource {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> std::io::Result<()> {
+        Err(IoError::from(ErrorKind::Unsupported))
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Err(IoError::from(ErrorKind::Unsupported)))
+    }
+}
+
+#[async_trait]
+impl AsyncMediaSource for LiveSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    async fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_playlist() {
+        let m3u = "#EXTM3U\n#EXTINF:-1,Groove Salad\nhttp://ice1.example.com/groove.mp3\n";
+        assert_eq!(
+            parse_playlist(m3u).as_deref(),
+            Some("http://ice1.example.com/groove.mp3")
+        );
+        let pls = "[playlist]\nNumberOfEntries=2\nFile1=https://a.example.com/live\nTitle1=A\nFile2=https://b.example.com/live\n";
+        assert_eq!(
+            parse_playlist(pls).as_deref(),
+            Some("https://a.example.com/live")
+        );
+        assert_eq!(parse_playlist("[playlist]\nNumberOfEntries=0\n"), None);
+    }
+
+    #[test]
+    fn test_handles() {
+        let stations = HashMap::from([(
+            "Lounge".to_owned(),
+            "https://radio.example.com:8000/lounge".to_owned(),
+        )]);
+        let resolver = RadioResolver::new(HttpClient::new(), &stations);
+        assert!(resolver.handles("https://radio.example.com:8000/lounge"));
+        assert!(resolver.handles("http://example.com/listen.pls"));
+        assert!(resolver.handles("http://example.com/station.M3U"));
+        assert!(resolver.handles("https://example.com/live/index.m3u8"));
+        assert!(!resolver.handles("https://radio.example.com:8000/other"));
+        assert!(!resolver.handles("https://www.youtube.com/watch?v=abc"));
+        assert!(!resolver.handles("lounge"));
+    }
+
+    #[tokio::test]
+    async fn test_detect_skips_audio_files() {
+        let resolver = RadioResolver::new(HttpClient::new(), &HashMap::new());
+        let detect = |query| resolver.detect(query, 1);
+        assert!(detect("https://example.invalid/song.mp3").await.is_none());
+        assert!(
+            detect("https://cdn.discordapp.com/attachments/1/2/Song.FLAC")
+                .await
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_looks_like_mount() {
+        let mount = |url: &str| looks_like_mount(&Url::parse(url).unwrap());
+        assert!(mount("http://radio.example.com:8000/lounge"));
+        assert!(mount("http://example.com/;"));
+        assert!(mount("https://ice1.somafm.com/groove-128-mp3"));
+        assert!(mount("https://stream.example.com/jazz"));
+        assert!(mount("https://example.com/listen/lounge"));
+        assert!(!mount("https://example.com/podcast/episode-12"));
+        assert!(!mount("https://soundcloud.example.com/artist/song"));
+    }
+
+    #[test]
+    fn test_is_live() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+                .collect::<HeaderMap>()
+        };
+        assert!(is_live(&headers(&[
+            ("content-type", "audio/mpeg"),
+            ("icy-name", "Lounge"),
+            ("content-length", "100"),
+        ])));
+        assert!(is_live(&headers(&[
+            ("content-type", "audio/aac"),
+            ("server", "Icecast 2.4.4"),
+        ])));
+        assert!(is_live(&headers(&[
+            ("content-type", "application/ogg"),
+            ("ice-audio-info", "bitrate=128"),
+        ])));
+        // Files served as they are generated don't say how long they are either
+        assert!(!is_live(&headers(&[("content-type", "audio/mpeg")])));
+        assert!(!is_live(&headers(&[
+            ("content-type", "audio/mpeg"),
+            ("content-length", "4000000"),
+        ])));
+        assert!(!is_live(&headers(&[("content-type", "text/html")])));
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use reqwest::Client as HttpClient;
+use serenity::async_trait;
+use songbird::input::{Input, YoutubeDl};
+
+use super::{PlayError, Resolved, ResolvedTrack, TrackResolver};
+use crate::{
+    player::TrackInfo,
+    spotify::{self, SpotifyError, SpotifyResolver},
+};
+
+/// Plays Spotify links by searching YouTube for every track
+#[derive(Debug, Clone)]
+pub struct SpotifyTrackResolver {
+    spotify: Option<Arc<dyn SpotifyResolver>>,
+    http_client: HttpClient,
+}
+
+impl SpotifyTrackResolver {
+    /// Without a Spotify client the links are still recognized, but refused with a clear error
+    pub fn new(
+        spotify: Option<Arc<dyn SpotifyResolver>>,
+        http_client: HttpClient,
+    ) -> SpotifyTrackResolver {
+        SpotifyTrackResolver {
+            spotify,
+            http_client,
+        }
+    }
+}
+
+#[async_trait]
+impl TrackResolver for SpotifyTrackResolver {
+    fn handles(&self, query: &str) -> bool {
+        spotify::parse_link(query).is_some()
+    }
+
+    async fn resolve(&self, query: &str, max_tracks: usize) -> Result<Resolved, PlayError> {
+        let link = spotify::parse_link(query)
+            .ok_or_else(|| PlayError::UnsupportedUrl(query.to_owned()))?;
+        let Some(spotify) = &self.spotify else {
+            return Err(PlayError::SourceDisabled("Spotify links"));
+        };
+        let resolved = spotify
+            .resolve(&link, max_tracks)
+            .await
+            .map_err(|e| match e {
+                SpotifyError::NotFound => PlayError::NoResults(query.to_owned()),
+                e => PlayError::ExtractorFailed(e.to_string()),
+            })?;
+
+        // The YouTube searches happen when each track is about to play, so long albums and
+        // playlists don't have to wait for dozens of searches up front.
+        let tracks = resolved
+            .tracks
+            .into_iter()
+            .map(|track| {
+                let info = TrackInfo {
+                    title: track.search_query(),
+                    url: track.url.clone().unwrap_or_else(|| query.to_owned()),
+                    duration: track.duration,
+                    requester: None,
+                    live: None,
+                };
+                let input = self.replay(&info);
+                ResolvedTrack { info, input }
+            })
+            .collect();
+        Ok(Resolved {
+            name: resolved.name,
+            tracks,
+        })
+    }
+
+    /// Spotify tracks are played from a YouTube search for the title
+    fn replay(&self, info: &TrackInfo) -> Input {
+        YoutubeDl::new_search(self.http_client.clone(), info.title.clone()).into()
+    }
+}
@@ -0,0 +1,188 @@
+use std::{fs::File, path::Path, time::Duration};
+
+use songbird::input::{
+    codecs::{CODEC_REGISTRY, PROBE},
+    Input, LiveInput, Parsed,
+};
+use symphonia::core::{
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::{Hint, ProbedMetadata},
+};
+
+use super::PlayError;
+
+/// What the tags and headers of an audio file tell us about the song
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl AudioTags {
+    /// Fill in whatever is still missing from a set of tags
+    fn fill(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            // Some taggers keep the NUL terminator of C strings in the value
+            let value = tag.value.to_string();
+            let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if field.is_none() && !value.is_empty() {
+                *field = Some(value.to_owned());
+            }
+        }
+    }
+
+    /// Title to show in the queue, with the artist in front when it is known
+    pub fn display_title(&self) -> Option<String> {
+        let title = self.title.as_ref()?;
+        Some(match &self.artist {
+            Some(artist) => format!("{} - {}", artist, title),
+            None => title.clone(),
+        })
+    }
+}
+
+/// Read the tags of an input that symphonia has already parsed
+pub fn read_tags(parsed: &mut Parsed) -> AudioTags {
+    collect_tags(
+        parsed.format.as_mut(),
+        &mut parsed.meta,
+        Some(parsed.track_id),
+    )
+}
+
+/// Read the tags of a file without setting up a decoder for it
+pub fn read_file_tags(path: &Path) -> Result<AudioTags, symphonia::core::errors::Error> {
+    let stream = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = PROBE.format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    Ok(collect_tags(
+        probed.format.as_mut(),
+        &mut probed.metadata,
+        None,
+    ))
+}
+
+/// Gather the tags from the container and from before it, like ID3 tags in front of an mp3.
+/// Without a track id the duration is read from the default track.
+fn collect_tags(
+    format: &mut dyn FormatReader,
+    probed: &mut ProbedMetadata,
+    track_id: Option<u32>,
+) -> AudioTags {
+    let mut tags = AudioTags::default();
+    if let Some(revision) = format.metadata().current() {
+        tags.fill(revision);
+    }
+    if let Some(revision) = probed.get().as_ref().and_then(|m| m.current()) {
+        tags.fill(revision);
+    }
+    let track = match track_id {
+        Some(id) => format.tracks().iter().find(|track| track.id == id),
+        None => format.default_track(),
+    };
+    tags.duration = track.and_then(|track| {
+        let frames = track.codec_params.n_frames?;
+        let sample_rate = track.codec_params.sample_rate?;
+        // Broken files can claim a sample rate of 0 or absurd lengths, which don't fit in a Duration
+        Duration::try_from_secs_f64(frames as f64 / f64::from(sample_rate)).ok()
+    });
+    tags
+}
+
+/// Open an input far enough to read its tags, for remote files this downloads the start
+pub async fn probe(input: Input) -> Result<AudioTags, PlayError> {
+    let mut input = input
+        .make_playable_async(&CODEC_REGISTRY, &PROBE)
+        .await
+        .map_err(|e| PlayError::ExtractorFailed(e.to_string()))?;
+    match &mut input {
+        Input::Live(LiveInput::Parsed(parsed), _) => Ok(read_tags(parsed)),
+        _ => Err(PlayError::ExtractorFailed(
+            "input wasn't parsed after making it playable".to_owned(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A second of silent 8kHz mono wav with the given title in its INFO chunk
+    fn wav(title: &str) -> Vec<u8> {
+        let samples = vec![0u8; 8000 * 2];
+        let mut name = title.as_bytes().to_vec();
+        name.push(0);
+        if name.len() % 2 == 1 {
+            name.push(0);
+        }
+
+        let mut info = b"INFO".to_vec();
+        info.extend(b"INAM");
+        info.extend((title.len() as u32 + 1).to_le_bytes());
+        info.extend(&name);
+
+        let mut fmt = Vec::new();
+        fmt.extend(1u16.to_le_bytes()); // PCM
+        fmt.extend(1u16.to_le_bytes()); // Channels
+        fmt.extend(8000u32.to_le_bytes()); // Sample rate
+        fmt.extend(16000u32.to_le_bytes()); // Byte rate
+        fmt.extend(2u16.to_le_bytes()); // Block align
+        fmt.extend(16u16.to_le_bytes()); // Bits per sample
+
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk) in [(b"fmt ", &fmt), (b"LIST", &info), (b"data", &samples)] {
+            body.extend(id);
+            body.extend((chunk.len() as u32).to_le_bytes());
+            body.extend(chunk);
+        }
+        let mut file = b"RIFF".to_vec();
+        file.extend((body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let tags = probe(Input::from(wav("Silence"))).await.unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Silence"));
+        assert_eq!(tags.duration, Some(Duration::from_secs(1)));
+        assert_eq!(tags.display_title().as_deref(), Some("Silence"));
+
+        let tags = AudioTags {
+            artist: Some("Nobody".to_owned()),
+            ..tags
+        };
+        assert_eq!(tags.display_title().as_deref(), Some("Nobody - Silence"));
+        assert!(probe(Input::from(b"not audio".to_vec())).await.is_err());
+    }
+
+    #[test]
+    fn test_read_file_tags() {
+        let path = std::env::temp_dir().join(format!("music_bot_tags_{}.wav", std::process::id()));
+        std::fs::write(&path, wav("From a file")).unwrap();
+        let tags = read_file_tags(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let tags = tags.unwrap();
+        assert_eq!(tags.title.as_deref(), Some("From a file"));
+        assert_eq!(tags.duration, Some(Duration::from_secs(1)));
+    }
+}
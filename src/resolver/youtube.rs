@@ -0,0 +1,396 @@
+use std::{io::ErrorKind, sync::Arc, time::Duration};
+
+use reqwest::{Client as HttpClient, Url};
+use serde::Deserialize;
+use serenity::async_trait;
+use songbird::input::{
+    AudioStream, AudioStreamError, AuxMetadata, Compose, File, Input, YoutubeDl,
+};
+use symphonia::core::io::MediaSource;
+use tokio::process::Command;
+
+use super::{PlayError, Resolved, ResolvedTrack, TrackResolver};
+use crate::{
+    cache::{self, AudioCache},
+    player::TrackInfo,
+};
+
+/// Plays anything yt-dlp understands and searches YouTube for everything that isn't a url
+#[derive(Debug, Clone)]
+pub struct YoutubeResolver {
+    http_client: HttpClient,
+    cache: Option<Arc<AudioCache>>,
+}
+
+impl YoutubeResolver {
+    pub fn new(http_client: HttpClient, cache: Option<Arc<AudioCache>>) -> YoutubeResolver {
+        YoutubeResolver { http_client, cache }
+    }
+
+    /// Wrap the stream of a video so it plays from the cache instead once it has been downloaded
+    fn cached(&self, url: &str, stream: YoutubeDl) -> Input {
+        let Some((cache, id)) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| Some((cache.clone(), cache::video_id(url)?)))
+        else {
+            return stream.into();
+        };
+        Input::Lazy(Box::new(CachedVideo {
+            cache,
+            id,
+            url: url.to_owned(),
+            stream,
+        }))
+    }
+}
+
+/// A video that is looked up in the cache when it is about to play rather than when it is queued,
+/// so only songs that actually play are downloaded and counted in the cache stats
+struct CachedVideo {
+    cache: Arc<AudioCache>,
+    id: String,
+    url: String,
+    stream: YoutubeDl,
+}
+
+#[async_trait]
+impl Compose for CachedVideo {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        self.stream.create()
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        match self.cache.get(&self.id) {
+            Some(path) => File::new(path).create_async().await,
+            None => {
+                // Played from YouTube this time, and from the cache next time
+                self.cache.download(self.id.clone(), self.url.clone());
+                self.stream.create_async().await
+            }
+        }
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        self.stream.aux_metadata().await
+    }
+}
+
+#[async_trait]
+impl TrackResolver for YoutubeResolver {
+    fn handles(&self, query: &str) -> bool {
+        query.starts_with("http://") || query.starts_with("https://") || !query.contains("://")
+    }
+
+    async fn resolve(&self, query: &str, max_tracks: usize) -> Result<Resolved, PlayError> {
+        if is_playlist_url(query) {
+            let playlist = list_playlist(query, max_tracks).await?;
+            let tracks = playlist
+                .entries
+                .into_iter()
+                .map(|entry| {
+                    let info = TrackInfo {
+                        title: entry.title.clone().unwrap_or_else(|| "Unknown".to_owned()),
+                        url: entry.url.clone(),
+                        duration: entry.duration(),
+                        requester: None,
+                        live: None,
+                    };
+                    let input = self.replay(&info);
+                    ResolvedTrack { info, input }
+                })
+                .collect();
+            return Ok(Resolved {
+                name: playlist.title,
+                tracks,
+            });
+        }
+
+        let mut src = if query.starts_with("http") {
+            YoutubeDl::new(self.http_client.clone(), query.to_owned())
+        } else {
+            YoutubeDl::new_search(self.http_client.clone(), query.to_owned())
+        };
+        let aux = first_result(&mut src, query).await?;
+        let info = TrackInfo {
+            title: aux.title.unwrap_or_else(|| "Unknown".to_owned()),
+            url: aux.source_url.unwrap_or_else(|| query.to_owned()),
+            duration: aux.duration,
+            requester: None,
+            live: None,
+        };
+        let input = self.cached(&info.url, src);
+        Ok(Resolved::single(info, input))
+    }
+
+    fn replay(&self, info: &TrackInfo) -> Input {
+        let stream = YoutubeDl::new(self.http_client.clone(), info.url.clone());
+        self.cached(&info.url, stream)
+    }
+}
+
+/// Something that can look up the metadata for a query, yt-dlp outside of tests
+#[async_trait]
+pub trait MetadataSearch: Send {
+    async fn search(
+        &mut self,
+        n_results: Option<usize>,
+    ) -> Result<Vec<AuxMetadata>, AudioStreamError>;
+}
+
+#[async_trait]
+impl MetadataSearch for YoutubeDl {
+    async fn search(
+        &mut self,
+        n_results: Option<usize>,
+    ) -> Result<Vec<AuxMetadata>, AudioStreamError> {
+        YoutubeDl::search(self, n_results).await
+    }
+}
+
+/// Get the metadata of up to `n_results` results for the query, failing if there are none
+pub async fn search(
+    src: &mut impl MetadataSearch,
+    query: &str,
+    n_results: usize,
+) -> Result<Vec<AuxMetadata>, PlayError> {
+    let results = src
+        .search(Some(n_results))
+        .await
+        .map_err(|e| PlayError::from_stream_error(query, e))?;
+    if results.is_empty() {
+        return Err(PlayError::NoResults(query.to_owned()));
+    }
+    Ok(results)
+}
+
+/// Get the metadata of the first result for the query
+async fn first_result(
+    src: &mut impl MetadataSearch,
+    query: &str,
+) -> Result<AuxMetadata, PlayError> {
+    Ok(search(src, query, 1).await?.swap_remove(0))
+}
+
+/// A playlist listed by yt-dlp without looking up the details of every entry
+#[derive(Debug, Clone, PartialEq)]
+struct Playlist {
+    title: Option<String>,
+    entries: Vec<PlaylistEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct PlaylistEntry {
+    url: String,
+    title: Option<String>,
+    duration: Option<f64>,
+}
+
+impl PlaylistEntry {
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+            .and_then(|d| Duration::try_from_secs_f64(d).ok())
+    }
+}
+
+/// Check if the url points to a whole YouTube playlist rather than a single video
+fn is_playlist_url(url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    let is_youtube = matches!(
+        url.host_str(),
+        Some("youtube.com" | "www.youtube.com" | "m.youtube.com" | "music.youtube.com")
+    );
+    is_youtube && url.path() == "/playlist" && url.query_pairs().any(|(k, _)| k == "list")
+}
+
+/// List up to `max_entries` entries of a playlist using the flat-playlist mode of yt-dlp
+async fn list_playlist(url: &str, max_entries: usize) -> Result<Playlist, PlayError> {
+    let output = Command::new("yt-dlp")
+        .args(["--flat-playlist", "-J", "--playlist-end"])
+        .arg(max_entries.to_string())
+        .arg(url)
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => PlayError::YtDlpMissing,
+            _ => PlayError::ExtractorFailed(e.to_string()),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(PlayError::from_message(url, stderr));
+    }
+
+    let playlist =
+        parse_playlist(&output.stdout).map_err(|e| PlayError::ExtractorFailed(e.to_string()))?;
+    if playlist.entries.is_empty() {
+        return Err(PlayError::NoResults(url.to_owned()));
+    }
+    Ok(playlist)
+}
+
+fn parse_playlist(json: &[u8]) -> Result<Playlist, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct RawPlaylist {
+        title: Option<String>,
+        #[serde(default)]
+        entries: Vec<RawEntry>,
+    }
+    #[derive(Deserialize)]
+    struct RawEntry {
+        url: Option<String>,
+        title: Option<String>,
+        duration: Option<f64>,
+    }
+
+    let raw: RawPlaylist = serde_json::from_slice(json)?;
+    let entries = raw
+        .entries
+        .into_iter()
+        // Private and deleted videos are still listed, but can't be played
+        .filter(|e| {
+            !matches!(
+                e.title.as_deref(),
+                Some("[Private video]" | "[Deleted video]")
+            )
+        })
+        .filter_map(|e| {
+            Some(PlaylistEntry {
+                url: e.url?,
+                title: e.title,
+                duration: e.duration,
+            })
+        })
+        .collect();
+    Ok(Playlist {
+        title: raw.title,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for yt-dlp, answering every search with the same result
+    struct StandIn(Option<Result<Vec<AuxMetadata>, AudioStreamError>>);
+
+    #[async_trait]
+    impl MetadataSearch for StandIn {
+        async fn search(
+            &mut self,
+            _n_results: Option<usize>,
+        ) -> Result<Vec<AuxMetadata>, AudioStreamError> {
+            self.0.take().expect("Only searched once.")
+        }
+    }
+
+    fn fail(msg: &str) -> StandIn {
+        StandIn(Some(Err(AudioStreamError::Fail(msg.into()))))
+    }
+
+    #[tokio::test]
+    async fn test_first_result() {
+        let aux = AuxMetadata {
+            title: Some("Song".to_owned()),
+            ..Default::default()
+        };
+        let mut src = StandIn(Some(Ok(vec![aux.clone(), AuxMetadata::default()])));
+        assert_eq!(first_result(&mut src, "song").await, Ok(aux));
+    }
+
+    #[tokio::test]
+    async fn test_no_results() {
+        let mut src = StandIn(Some(Ok(vec![])));
+        assert_eq!(
+            first_result(&mut src, "nothing").await,
+            Err(PlayError::NoResults("nothing".to_owned()))
+        );
+
+        let mut src = fail("no results found for 'ytsearch1:nothing'");
+        assert_eq!(
+            first_result(&mut src, "nothing").await,
+            Err(PlayError::NoResults("nothing".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_yt_dlp_missing() {
+        let mut src = fail("could not find executable 'yt-dlp' on path");
+        assert_eq!(
+            first_result(&mut src, "song").await,
+            Err(PlayError::YtDlpMissing)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_url() {
+        let mut src = fail(
+            "yt-dlp failed with non-zero status code: ERROR: Unsupported URL: https://example.com",
+        );
+        assert_eq!(
+            first_result(&mut src, "https://example.com").await,
+            Err(PlayError::UnsupportedUrl("https://example.com".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extractor_failed() {
+        let msg = "yt-dlp failed with non-zero status code: ERROR: Video unavailable";
+        let mut src = fail(msg);
+        assert_eq!(
+            first_result(&mut src, "https://youtu.be/x").await,
+            Err(PlayError::ExtractorFailed(format!(
+                "failed to create audio: {}",
+                msg
+            )))
+        );
+        assert!(!PlayError::ExtractorFailed(msg.to_owned())
+            .to_string()
+            .contains("Video unavailable"));
+    }
+
+    #[test]
+    fn test_is_playlist_url() {
+        assert!(is_playlist_url(
+            "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+        ));
+        assert!(is_playlist_url(
+            "https://music.youtube.com/playlist?list=abc"
+        ));
+        assert!(!is_playlist_url(
+            "https://www.youtube.com/watch?v=abc&list=abc"
+        ));
+        assert!(!is_playlist_url("https://example.com/playlist?list=abc"));
+        assert!(!is_playlist_url("never gonna give you up"));
+    }
+
+    #[test]
+    fn test_parse_playlist() {
+        let json = br#"{
+            "title": "Mix",
+            "entries": [
+                {"url": "https://www.youtube.com/watch?v=a", "title": "A", "duration": 61.0},
+                {"url": "https://www.youtube.com/watch?v=b", "title": "[Private video]"},
+                {"url": "https://www.youtube.com/watch?v=c", "title": "C", "duration": null},
+                {"title": "No url"}
+            ]
+        }"#;
+        let playlist = parse_playlist(json).unwrap();
+        assert_eq!(playlist.title.as_deref(), Some("Mix"));
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(
+            playlist.entries[0].duration(),
+            Some(Duration::from_secs(61))
+        );
+        assert_eq!(playlist.entries[1].title.as_deref(), Some("C"));
+        assert_eq!(playlist.entries[1].duration(), None);
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, Context as SerenityContext, GuildId, Ready, UserId};
+use songbird::Call;
+use tokio::sync::Mutex;
+
+use crate::{
+    always_on, guild_state::GuildStates, player, player::TrackInfo, storage::Storage, Data,
+};
+
+pub const SAVED_QUEUES_PATH: &str = "./data/queues.json";
+
+/// How far playback has to get before a save writes down the new position
+const POSITION_SLACK: Duration = Duration::from_secs(30);
+
+/// The saved queues of every guild, along with which change to each of them was written last
+#[derive(Debug)]
+pub struct SavedQueues {
+    queues: Arc<Storage<HashMap<GuildId, SavedQueue>>>,
+    /// Handed out to every save in the order the queues were looked at
+    next_change: AtomicU64,
+    /// Latest change written for each guild, so a save that finishes late is dropped
+    written: parking_lot::Mutex<HashMap<GuildId, u64>>,
+}
+
+impl SavedQueues {
+    pub fn load(path: impl Into<PathBuf>) -> SavedQueues {
+        SavedQueues {
+            queues: Arc::new(Storage::load(path)),
+            next_change: AtomicU64::new(0),
+            written: Default::default(),
+        }
+    }
+
+    pub fn get<R>(&self, f: impl FnOnce(&HashMap<GuildId, SavedQueue>) -> R) -> R {
+        self.queues.get(f)
+    }
+
+    /// Number a new change to a queue, taken while the call is locked so the numbers follow the
+    /// order the queue really changed in
+    fn change(&self) -> u64 {
+        self.next_change.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Write the queue of the guild, or drop it if there is none, unless a newer change was
+    /// written already
+    fn store(&self, guild_id: GuildId, change: u64, queue: Option<SavedQueue>) {
+        let mut written = self.written.lock();
+        if written.get(&guild_id).is_some_and(|&last| last > change) {
+            return;
+        }
+        written.insert(guild_id, change);
+
+        // Whether the new queue is about the same as the saved one, None if nothing is saved
+        let unchanged = self.queues.get(|queues| {
+            let saved = queues.get(&guild_id)?;
+            Some(queue.as_ref().is_some_and(|queue| saved.is_close_to(queue)))
+        });
+        match (unchanged, queue) {
+            (Some(true), _) => {}
+            (_, Some(queue)) => {
+                self.queues
+                    .update_later(|queues| queues.insert(guild_id, queue));
+            }
+            (Some(_), None) => {
+                self.queues.update_later(|queues| queues.remove(&guild_id));
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// The queue of a guild as it was when it last changed, so it can be restored after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQueue {
+    pub voice_channel: ChannelId,
+    pub text_channel: Option<ChannelId>,
+    /// The track that was playing first, followed by the ones waiting in the queue
+    pub tracks: Vec<SavedTrack>,
+    /// How far into the first track playback got
+    pub position: Duration,
+}
+
+impl SavedQueue {
+    /// Whether the queues only differ in a position change too small to be worth writing down
+    fn is_close_to(&self, other: &SavedQueue) -> bool {
+        self.voice_channel == other.voice_channel
+            && self.text_channel == other.text_channel
+            && self.tracks == other.tracks
+            && self.position.abs_diff(other.position) < POSITION_SLACK
+    }
+
+    /// Where to pick the first track up again, live streams always start from where they are now
+    fn resume_at(&self) -> Option<Duration> {
+        let first = self.tracks.first()?;
+        (!first.live && !self.position.is_zero()).then_some(self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedTrack {
+    pub url: String,
+    pub title: String,
+    pub requester: Option<UserId>,
+    pub duration: Option<Duration>,
+    #[serde(default)]
+    pub live: bool,
+}
+
+impl SavedTrack {
+    pub fn new(info: TrackInfo) -> SavedTrack {
+        SavedTrack {
+            url: info.url,
+            title: info.title,
+            requester: info.requester,
+            duration: info.duration,
+            live: info.live.is_some(),
+        }
+    }
+
+    pub fn info(&self) -> TrackInfo {
+        TrackInfo {
+            title: self.title.clone(),
+            url: self.url.clone(),
+            duration: self.duration,
+            requester: self.requester,
+            // What the station is playing shows up again once the stream is read
+            live: self.live.then(Default::default),
+        }
+    }
+}
+
+/// Write the current queue of the guild to disk, forgetting it once the queue is empty
+pub async fn save(
+    saved_queues: &SavedQueues,
+    guild_states: &GuildStates,
+    guild_id: GuildId,
+    call_lock: &Mutex<Call>,
+) {
+    let call = call_lock.lock().await;
+    let Some(channel) = call.current_channel() else {
+        return;
+    };
+    let handles = call.queue().current_queue();
+    let change = saved_queues.change();
+    drop(call);
+
+    if handles.is_empty() {
+        saved_queues.store(guild_id, change, None);
+        return;
+    }
+    let position = match handles[0].get_info().await {
+        Ok(state) => state.position,
+        Err(_) => Duration::ZERO,
+    };
+    let mut tracks = Vec::with_capacity(handles.len());
+    for handle in &handles {
+        tracks.push(SavedTrack::new(TrackInfo::from_handle(handle).await));
+    }
+    let saved = SavedQueue {
+        voice_channel: ChannelId::new(channel.0.get()),
+        text_channel: guild_states.get(guild_id, |state| state.text_channel),
+        tracks,
+        position,
+    };
+    saved_queues.store(guild_id, change, Some(saved));
+}
+
+/// Drop the saved queue of the guild, used when the bot leaves on purpose
+pub fn forget(saved_queues: &SavedQueues, guild_id: GuildId) {
+    // Counts as the newest change, so saves still running from before leaving can't bring it back
+    saved_queues.store(guild_id, saved_queues.change(), None);
+}
+
+/// Add the saved tracks of the guild back to the queue if nothing else is queued and seek to
+/// where the first one was, returning how many tracks were restored
+pub async fn restore(data: &Data, guild_id: GuildId, call_lock: &Mutex<Call>) -> usize {
+    let Some(saved) = data
+        .saved_queues
+        .get(|queues| queues.get(&guild_id).cloned())
+    else {
+        return 0;
+    };
+
+    let volume = f32::from(data.guild_volume(guild_id)) / 100.0;
+    let mut call = call_lock.lock().await;
+    // Whatever got queued since is newer than the saved queue
+    if !call.queue().is_empty() {
+        return 0;
+    }
+    let resume_at = saved.resume_at();
+    let mut restored = 0;
+    for (i, track) in saved.tracks.iter().enumerate() {
+        let info = track.info();
+        let Some(input) = data.resolvers.replay(&info) else {
+            tracing::warn!(
+                "Don't know how to play \"{}\" again, dropping it.",
+                info.url
+            );
+            continue;
+        };
+        let handle = player::enqueue(&mut call, input, info, volume).await;
+        restored += 1;
+
+        if let (0, Some(position)) = (i, resume_at) {
+            tokio::spawn(async move {
+                if let Err(e) = player::seek(&handle, position).await {
+                    tracing::warn!(err = %e, "Failed to seek to the saved position.");
+                }
+            });
+        }
+    }
+    restored
+}
+
+/// Let guilds know their queue can be restored, called on every ready event
+pub async fn on_ready(ctx: &SerenityContext, data: &Data, ready: &Ready) {
+    let Some(manager) = songbird::get(ctx).await else {
+        return;
+    };
+    for guild in &ready.guilds {
+        // Guilds in 24/7 mode get their queue back by themselves, and a guild the bot is still
+        // in after reconnecting hasn't lost anything
+        if always_on::pinned_channel(data, guild.id).is_some() || manager.get(guild.id).is_some() {
+            continue;
+        }
+        let saved = data.saved_queues.get(|queues| {
+            queues
+                .get(&guild.id)
+                .and_then(|saved| Some((saved.text_channel?, saved.tracks.len())))
+        });
+        let Some((text_channel, count)) = saved else {
+            continue;
+        };
+        let notice = format!(
+            "I was restarted with {} songs in the queue, use /restore to pick up where we left off.",
+            count
+        );
+        if let Err(e) = text_channel.say(ctx, notice).await {
+            tracing::warn!(err = %e, "Failed to offer restoring the queue.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::resolver::StreamTitle;
+
+    use super::*;
+
+    #[test]
+    fn test_saved_track_roundtrip() {
+        let info = TrackInfo {
+            title: "Song".to_owned(),
+            url: "https://example.com/song.mp3".to_owned(),
+            duration: Some(Duration::from_secs(90)),
+            requester: Some(UserId::new(1234)),
+            live: None,
+        };
+        let saved = SavedTrack::new(info);
+        let json = serde_json::to_string(&saved).unwrap();
+        let loaded: SavedTrack = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, saved);
+
+        let info = loaded.info();
+        assert_eq!(info.title, "Song");
+        assert_eq!(info.duration, Some(Duration::from_secs(90)));
+        assert_eq!(info.requester, Some(UserId::new(1234)));
+        assert!(info.live.is_none());
+
+        let radio = TrackInfo {
+            title: "Radio".to_owned(),
+            url: "https://example.com/stream".to_owned(),
+            duration: None,
+            requester: None,
+            live: Some(StreamTitle::default()),
+        };
+        assert!(SavedTrack::new(radio).info().live.is_some());
+    }
+
+    fn track(title: &str, live: bool) -> SavedTrack {
+        SavedTrack {
+            url: format!("https://example.com/{}", title),
+            title: title.to_owned(),
+            requester: None,
+            duration: (!live).then_some(Duration::from_secs(180)),
+            live,
+        }
+    }
+
+    fn queue(titles: &[&str], position: Duration) -> SavedQueue {
+        SavedQueue {
+            voice_channel: ChannelId::new(1),
+            text_channel: Some(ChannelId::new(2)),
+            tracks: titles.iter().map(|title| track(title, false)).collect(),
+            position,
+        }
+    }
+
+    fn titles(saved_queues: &SavedQueues, guild_id: GuildId) -> Option<Vec<String>> {
+        saved_queues.get(|queues| {
+            let saved = queues.get(&guild_id)?;
+            Some(saved.tracks.iter().map(|t| t.title.clone()).collect())
+        })
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "music_bot_queues_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Wait for the writes in the background to finish before removing the file
+    async fn remove_when_written(saved_queues: &SavedQueues, path: &Path) {
+        saved_queues.queues.flush().await;
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_save_and_restore_keep_order_and_position() {
+        let path = temp_path("roundtrip");
+        let guild_id = GuildId::new(1234);
+
+        let saved_queues = SavedQueues::load(&path);
+        let change = saved_queues.change();
+        let saved = queue(&["a", "b", "c"], Duration::from_secs(42));
+        saved_queues.store(guild_id, change, Some(saved));
+
+        // Written to disk in the background
+        saved_queues.queues.flush().await;
+        let saved_queues = SavedQueues::load(&path);
+        assert_eq!(
+            titles(&saved_queues, guild_id),
+            Some(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()])
+        );
+        let resume_at = saved_queues.get(|queues| queues[&guild_id].resume_at());
+        assert_eq!(resume_at, Some(Duration::from_secs(42)));
+
+        remove_when_written(&saved_queues, &path).await;
+    }
+
+    #[test]
+    fn test_resume_at() {
+        assert_eq!(queue(&["a"], Duration::ZERO).resume_at(), None);
+        assert_eq!(queue(&[], Duration::from_secs(5)).resume_at(), None);
+
+        let mut radio = queue(&["a"], Duration::from_secs(5));
+        radio.tracks[0] = track("radio", true);
+        assert_eq!(radio.resume_at(), None);
+    }
+
+    #[tokio::test]
+    async fn test_late_saves_are_dropped() {
+        let path = temp_path("ordering");
+        let guild_id = GuildId::new(1234);
+        let saved_queues = SavedQueues::load(&path);
+
+        // The first save finishes after the second one
+        let first = saved_queues.change();
+        let second = saved_queues.change();
+        saved_queues.store(guild_id, second, Some(queue(&["b"], Duration::ZERO)));
+        saved_queues.store(guild_id, first, Some(queue(&["a"], Duration::ZERO)));
+        assert_eq!(titles(&saved_queues, guild_id), Some(vec!["b".to_owned()]));
+
+        // Only the guild the newer save was for is affected
+        let other = GuildId::new(5678);
+        saved_queues.store(other, first, Some(queue(&["c"], Duration::ZERO)));
+        assert_eq!(titles(&saved_queues, other), Some(vec!["c".to_owned()]));
+
+        // A save that was already running when the bot left doesn't bring the queue back
+        let running = saved_queues.change();
+        forget(&saved_queues, guild_id);
+        saved_queues.store(guild_id, running, Some(queue(&["a"], Duration::ZERO)));
+        assert_eq!(titles(&saved_queues, guild_id), None);
+
+        remove_when_written(&saved_queues, &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_small_position_changes() {
+        let path = temp_path("position");
+        let guild_id = GuildId::new(1234);
+        let saved_queues = SavedQueues::load(&path);
+        let position =
+            |saved_queues: &SavedQueues| saved_queues.get(|queues| queues[&guild_id].position);
+
+        let change = saved_queues.change();
+        let saved = queue(&["a", "b"], Duration::from_secs(10));
+        saved_queues.store(guild_id, change, Some(saved));
+        let change = saved_queues.change();
+        let saved = queue(&["a", "b"], Duration::from_secs(20));
+        saved_queues.store(guild_id, change, Some(saved));
+        assert_eq!(position(&saved_queues), Duration::from_secs(10));
+
+        let change = saved_queues.change();
+        let saved = queue(&["a", "b"], Duration::from_secs(60));
+        saved_queues.store(guild_id, change, Some(saved));
+        assert_eq!(position(&saved_queues), Duration::from_secs(60));
+
+        // Any other change is written right away
+        let change = saved_queues.change();
+        let saved = queue(&["b"], Duration::from_secs(61));
+        saved_queues.store(guild_id, change, Some(saved));
+        assert_eq!(position(&saved_queues), Duration::from_secs(61));
+
+        remove_when_written(&saved_queues, &path).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_after_forgetting() {
+        let path = temp_path("forget");
+        let guild_id = GuildId::new(1234);
+        let saved_queues = SavedQueues::load(&path);
+        let change = saved_queues.change();
+        saved_queues.store(guild_id, change, Some(queue(&["a"], Duration::ZERO)));
+
+        // Leaving removes the call before forgetting, so a save after that finds no channel
+        forget(&saved_queues, guild_id);
+        let call = Mutex::new(Call::standalone(guild_id, UserId::new(1)));
+        save(&saved_queues, &GuildStates::default(), guild_id, &call).await;
+        assert_eq!(titles(&saved_queues, guild_id), None);
+
+        remove_when_written(&saved_queues, &path).await;
+    }
+}
@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId};
+
+use crate::storage::Storage;
+
+pub type Settings = Storage<HashMap<GuildId, GuildSettings>>;
+
+pub const SETTINGS_PATH: &str = "./data/settings.json";
+
+/// Settings chosen by a guild that should survive restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildSettings {
+    /// Volume in percent, where 100 is the original volume of the song
+    #[serde(default)]
+    pub volume: Option<u8>,
+    /// Voice channel the bot stays in around the clock, set with the 247 command
+    #[serde(default)]
+    pub always_on_channel: Option<ChannelId>,
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use reqwest::{Client as HttpClient, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use serenity::async_trait;
+use tokio::sync::Mutex;
+
+/// What a Spotify link points to, along with its id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpotifyLink {
+    Track(String),
+    Album(String),
+    Playlist(String),
+}
+
+/// Parse links like `https://open.spotify.com/track/<id>` and `spotify:album:<id>`
+pub fn parse_link(link: &str) -> Option<SpotifyLink> {
+    let (kind, id) = if let Some(uri) = link.strip_prefix("spotify:") {
+        let (kind, id) = uri.split_once(':')?;
+        (kind.to_owned(), id.to_owned())
+    } else {
+        let url = Url::parse(link).ok()?;
+        if url.host_str() != Some("open.spotify.com") {
+            return None;
+        }
+        // Localized links look like /intl-de/track/<id>
+        let mut segments = url
+            .path_segments()?
+            .filter(|s| !s.is_empty() && !s.starts_with("intl-"));
+        let kind = segments.next()?.to_owned();
+        let id = segments.next()?.to_owned();
+        (kind, id)
+    };
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    match kind.as_str() {
+        "track" => Some(SpotifyLink::Track(id)),
+        "album" => Some(SpotifyLink::Album(id)),
+        "playlist" => Some(SpotifyLink::Playlist(id)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpotifyTrack {
+    pub artists: Vec<String>,
+    pub title: String,
+    pub duration: Option<Duration>,
+    /// Link to the track on Spotify
+    pub url: Option<String>,
+}
+
+impl SpotifyTrack {
+    /// What to search YouTube for to find this track
+    pub fn search_query(&self) -> String {
+        if self.artists.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} - {}", self.artists.join(", "), self.title)
+        }
+    }
+}
+
+/// The tracks behind a Spotify link, with the name of the album or playlist if it was one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpotifyTracks {
+    pub name: Option<String>,
+    pub tracks: Vec<SpotifyTrack>,
+}
+
+#[derive(Debug)]
+pub enum SpotifyError {
+    NotFound,
+    Request(String),
+}
+
+impl fmt::Display for SpotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpotifyError::NotFound => write!(f, "not found on Spotify"),
+            SpotifyError::Request(e) => write!(f, "request to Spotify failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SpotifyError {}
+
+impl From<reqwest::Error> for SpotifyError {
+    fn from(e: reqwest::Error) -> Self {
+        SpotifyError::Request(e.to_string())
+    }
+}
+
+/// Looks up the tracks behind Spotify links
+#[async_trait]
+pub trait SpotifyResolver: fmt::Debug + Send + Sync {
+    /// Get up to `max_tracks` tracks from the link
+    async fn resolve(
+        &self,
+        link: &SpotifyLink,
+        max_tracks: usize,
+    ) -> Result<SpotifyTracks, SpotifyError>;
+}
+
+/// Resolves links with the Spotify Web API, using the client credentials flow
+pub struct SpotifyApi {
+    http: HttpClient,
+    api_url: String,
+    accounts_url: String,
+    client_id: String,
+    client_secret: String,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl SpotifyApi {
+    pub fn new(http: HttpClient, client_id: String, client_secret: String) -> SpotifyApi {
+        SpotifyApi::with_urls(
+            http,
+            client_id,
+            client_secret,
+            "https://api.spotify.com".to_owned(),
+            "https://accounts.spotify.com".to_owned(),
+        )
+    }
+
+    fn with_urls(
+        http: HttpClient,
+        client_id: String,
+        client_secret: String,
+        api_url: String,
+        accounts_url: String,
+    ) -> SpotifyApi {
+        SpotifyApi {
+            http,
+            api_url,
+            accounts_url,
+            client_id,
+            client_secret,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Get an access token, reusing the last one until it is about to expire
+    async fn token(&self) -> Result<String, SpotifyError> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            expires_in: u64,
+        }
+
+        let mut token = self.token.lock().await;
+        if let Some((token, expires)) = token.as_ref() {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let res = self
+            .http
+            .post(format!("{}/api/token", self.accounts_url))
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await?
+            .error_for_status()?;
+        let res: TokenResponse = serde_json::from_slice(&res.bytes().await?)
+            .map_err(|e| SpotifyError::Request(e.to_string()))?;
+        let expires = Instant::now() + Duration::from_secs(res.expires_in.saturating_sub(60));
+        *token = Some((res.access_token.clone(), expires));
+        Ok(res.access_token)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, SpotifyError> {
+        let res = self
+            .http
+            .get(url)
+            .bearer_auth(self.token().await?)
+            .send()
+            .await?;
+        // Spotify answers with 400 for ids that aren't valid at all
+        if matches!(res.status().as_u16(), 400 | 404) {
+            return Err(SpotifyError::NotFound);
+        }
+        let bytes = res.error_for_status()?.bytes().await?;
+        serde_json::from_slice(&bytes).map_err(|e| SpotifyError::Request(e.to_string()))
+    }
+
+    /// Follow the `next` links of a paged list of items until we have enough
+    async fn collect_pages<T: DeserializeOwned>(
+        &self,
+        mut page: Page<T>,
+        max_items: usize,
+    ) -> Result<Vec<T>, SpotifyError> {
+        let mut items = vec![];
+        loop {
+            items.extend(page.items);
+            match page.next {
+                Some(next) if items.len() < max_items => page = self.get(&next).await?,
+                _ => break,
+            }
+        }
+        items.truncate(max_items);
+        Ok(items)
+    }
+}
+
+impl fmt::Debug for SpotifyApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpotifyApi")
+            .field("api_url", &self.api_url)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiTrack {
+    name: String,
+    duration_ms: Option<u64>,
+    #[serde(default)]
+    artists: Vec<ApiArtist>,
+    #[serde(default)]
+    external_urls: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ApiArtist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiAlbum {
+    name: String,
+    tracks: Page<ApiTrack>,
+}
+
+#[derive(Deserialize)]
+struct ApiPlaylist {
+    name: String,
+    tracks: Page<ApiPlaylistItem>,
+}
+
+#[derive(Deserialize)]
+struct ApiPlaylistItem {
+    /// Missing for tracks that have been removed from Spotify
+    track: Option<ApiTrack>,
+}
+
+impl From<ApiTrack> for SpotifyTrack {
+    fn from(mut track: ApiTrack) -> Self {
+        SpotifyTrack {
+            artists: track.artists.into_iter().map(|a| a.name).collect(),
+            title: track.name,
+            duration: track.duration_ms.map(Duration::from_millis),
+            url: track.external_urls.remove("spotify"),
+        }
+    }
+}
+
+#[async_trait]
+impl SpotifyResolver for SpotifyApi {
+    async fn resolve(
+        &self,
+        link: &SpotifyLink,
+        max_tracks: usize,
+    ) -> Result<SpotifyTracks, SpotifyError> {
+        match link {
+            SpotifyLink::Track(id) => {
+                let track: ApiTrack = self
+                    .get(&format!("{}/v1/tracks/{}", self.api_url, id))
+                    .await?;
+                Ok(SpotifyTracks {
+                    name: None,
+                    tracks: vec![track.into()],
+                })
+            }
+            SpotifyLink::Album(id) => {
+                let album: ApiAlbum = self
+                    .get(&format!("{}/v1/albums/{}", self.api_url, id))
+                    .await?;
+                let tracks = self.collect_pages(album.tracks, max_tracks).await?;
+                Ok(SpotifyTracks {
+                    name: Some(album.name),
+                    tracks: tracks.into_iter().map(SpotifyTrack::from).collect(),
+                })
+            }
+            SpotifyLink::Playlist(id) => {
+                let url = format!("{}/v1/playlists/{}", self.api_url, id);
+                let playlist: ApiPlaylist = self.get(&url).await?;
+                let items = self.collect_pages(playlist.tracks, max_tracks).await?;
+                Ok(SpotifyTracks {
+                    name: Some(playlist.name),
+                    tracks: items
+                        .into_iter()
+                        .filter_map(|item| item.track)
+                        .map(SpotifyTrack::from)
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse_link() {
+        assert_eq!(
+            parse_link("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc"),
+            Some(SpotifyLink::Track("4uLU6hMCjMI75M1A2tKUQC".to_owned()))
+        );
+        assert_eq!(
+            parse_link("https://open.spotify.com/intl-de/album/1ATL5GLyefJaxhQzSPVrLX"),
+            Some(SpotifyLink::Album("1ATL5GLyefJaxhQzSPVrLX".to_owned()))
+        );
+        assert_eq!(
+            parse_link("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+            Some(SpotifyLink::Playlist("37i9dQZF1DXcBWIGoYBM5M".to_owned()))
+        );
+        assert_eq!(parse_link("https://open.spotify.com/artist/abc"), None);
+        assert_eq!(parse_link("https://example.com/track/abc"), None);
+        assert_eq!(parse_link("rick astley"), None);
+    }
+
+    /// Serve canned json responses by path until the test ends, returning the base url
+    async fn mock_server(routes: HashMap<&'static str, String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 8192];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or("");
+                let response = match routes.get(path) {
+                    Some(body) => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                        .to_owned(),
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        base_url
+    }
+
+    fn track_json(name: &str, artist: &str) -> String {
+        format!(
+            r#"{{"name": "{}", "duration_ms": 213000, "artists": [{{"name": "{}"}}], "external_urls": {{"spotify": "https://open.spotify.com/track/{}"}}}}"#,
+            name, artist, name
+        )
+    }
+
+    async fn api(routes: HashMap<&'static str, String>) -> SpotifyApi {
+        let mut routes = routes;
+        routes.insert(
+            "/api/token",
+            r#"{"access_token": "token", "token_type": "Bearer", "expires_in": 3600}"#.to_owned(),
+        );
+        let base_url = mock_server(routes).await;
+        SpotifyApi::with_urls(
+            HttpClient::new(),
+            "id".to_owned(),
+            "secret".to_owned(),
+            base_url.clone(),
+            base_url,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_track() {
+        let api = api(HashMap::from([(
+            "/v1/tracks/abc",
+            track_json("Never Gonna Give You Up", "Rick Astley"),
+        )]))
+        .await;
+
+        let tracks = api
+            .resolve(&SpotifyLink::Track("abc".to_owned()), 10)
+            .await
+            .unwrap();
+        assert_eq!(tracks.name, None);
+        assert_eq!(tracks.tracks.len(), 1);
+        assert_eq!(
+            tracks.tracks[0].search_query(),
+            "Rick Astley - Never Gonna Give You Up"
+        );
+        assert_eq!(tracks.tracks[0].duration, Some(Duration::from_secs(213)));
+        assert_eq!(
+            tracks.tracks[0].url.as_deref(),
+            Some("https://open.spotify.com/track/Never Gonna Give You Up")
+        );
+
+        let missing = api
+            .resolve(&SpotifyLink::Track("nope".to_owned()), 10)
+            .await;
+        assert!(matches!(missing, Err(SpotifyError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_album_and_playlist() {
+        let routes = HashMap::from([
+            (
+                "/v1/albums/abc",
+                format!(
+                    r#"{{"name": "Album", "tracks": {{"items": [{}, {}], "next": null}}}}"#,
+                    track_json("One", "A"),
+                    track_json("Two", "B")
+                ),
+            ),
+            (
+                "/v1/playlists/abc",
+                format!(
+                    r#"{{"name": "Playlist", "tracks": {{"items": [{{"track": {}}}, {{"track": null}}, {{"track": {}}}], "next": null}}}}"#,
+                    track_json("One", "A"),
+                    track_json("Three", "C")
+                ),
+            ),
+        ]);
+        let api = api(routes).await;
+
+        let album = api
+            .resolve(&SpotifyLink::Album("abc".to_owned()), 10)
+            .await
+            .unwrap();
+        assert_eq!(album.name.as_deref(), Some("Album"));
+        assert_eq!(album.tracks.len(), 2);
+        assert_eq!(album.tracks[1].search_query(), "B - Two");
+
+        let playlist = api
+            .resolve(&SpotifyLink::Playlist("abc".to_owned()), 10)
+            .await
+            .unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Playlist"));
+        assert_eq!(playlist.tracks.len(), 2);
+        assert_eq!(playlist.tracks[1].title, "Three");
+
+        let limited = api
+            .resolve(&SpotifyLink::Album("abc".to_owned()), 1)
+            .await
+            .unwrap();
+        assert_eq!(limited.tracks.len(), 1);
+    }
+}
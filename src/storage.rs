@@ -0,0 +1,160 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::task::JoinHandle;
+
+/// A value that is kept in memory and written to a json file every time it changes
+pub struct Storage<T> {
+    path: PathBuf,
+    value: Mutex<T>,
+    /// Held while writing so writes never overlap, always taken before `value`
+    writing: Mutex<()>,
+    /// Whether a write on a blocking thread is waiting to start
+    write_queued: AtomicBool,
+    /// The latest write started on a blocking thread, which includes every change before it
+    last_write: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> Storage<T> {
+    /// Load the value from the given path, starting from the default if the file is missing or
+    /// broken
+    pub fn load(path: impl Into<PathBuf>) -> Storage<T> {
+        let path = path.into();
+        let value = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+                tracing::error!(err = %e, "Failed to parse {}, starting from scratch.", path.display());
+                T::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+            Err(e) => {
+                tracing::error!(err = %e, "Failed to read {}, starting from scratch.", path.display());
+                T::default()
+            }
+        };
+        Storage {
+            path,
+            value: Mutex::new(value),
+            writing: Mutex::new(()),
+            write_queued: AtomicBool::new(false),
+            last_write: Mutex::new(None),
+        }
+    }
+
+    pub fn get<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.value.lock())
+    }
+
+    /// Change the value and write it to disk on a blocking thread, so writing never holds up the
+    /// async code changing it. Changes made before the write starts are written along with it.
+    pub fn update_later<R>(self: &Arc<Self>, f: impl FnOnce(&mut T) -> R) -> R
+    where
+        T: Send + 'static,
+    {
+        let ret = f(&mut self.value.lock());
+        if !self.write_queued.swap(true, Ordering::AcqRel) {
+            let storage = self.clone();
+            let write = tokio::task::spawn_blocking(move || {
+                let _writing = storage.writing.lock();
+                storage.write_queued.store(false, Ordering::Release);
+                let json = serde_json::to_string_pretty(&*storage.value.lock());
+                storage.write(json);
+            });
+            *self.last_write.lock() = Some(write);
+        }
+        ret
+    }
+
+    /// Wait until the changes made with `update_later` so far are on disk
+    #[cfg(test)]
+    pub async fn flush(&self) {
+        let write = self.last_write.lock().take();
+        if let Some(write) = write {
+            write.await.expect("Writing to disk panicked.");
+        }
+    }
+
+    fn write(&self, json: serde_json::Result<String>) {
+        if let Err(e) = json
+            .map_err(Into::into)
+            .and_then(|json| save(&self.path, &json))
+        {
+            tracing::error!(err = %e, "Failed to save {}.", self.path.display());
+        }
+    }
+}
+
+fn save(path: &Path, json: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary file first so a crash never leaves a half written file behind
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, json)?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+impl<T> fmt::Debug for Storage<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Storage").field("path", &self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serenity::all::GuildId;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_storage_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("music_bot_storage_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let storage = Arc::new(Storage::<HashMap<GuildId, u8>>::load(&path));
+        assert!(storage.get(|map| map.is_empty()));
+        storage.update_later(|map| map.insert(GuildId::new(1234), 50));
+        storage.flush().await;
+
+        let storage = Storage::<HashMap<GuildId, u8>>::load(&path);
+        assert_eq!(
+            storage.get(|map| map.get(&GuildId::new(1234)).cloned()),
+            Some(50)
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_later() {
+        let path = std::env::temp_dir().join(format!(
+            "music_bot_storage_later_{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let storage = Arc::new(Storage::<HashMap<GuildId, u8>>::load(&path));
+        for volume in 0..10 {
+            storage.update_later(|map| map.insert(GuildId::new(1234), volume));
+        }
+        assert_eq!(storage.get(|map| map[&GuildId::new(1234)]), 9);
+
+        // The write happens in the background, the last change has to end up on disk
+        storage.flush().await;
+        let storage = Storage::<HashMap<GuildId, u8>>::load(&path);
+        assert_eq!(storage.get(|map| map[&GuildId::new(1234)]), 9);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::{fmt, time::Duration};
+
+/// Where a seek should end up, either an absolute position or relative to the current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekTarget {
+    Absolute(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+
+impl SeekTarget {
+    /// Resolve the target against the current position, stopping at the start of the song
+    pub fn resolve(self, current: Duration) -> Result<Duration, TimestampError> {
+        match self {
+            SeekTarget::Absolute(position) => Ok(position),
+            SeekTarget::Forward(offset) => {
+                current.checked_add(offset).ok_or(TimestampError::TooLarge)
+            }
+            SeekTarget::Backward(offset) => Ok(current.saturating_sub(offset)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimestampError {
+    Empty,
+    InvalidNumber(String),
+    TooManyParts,
+    OutOfRange(String),
+    TooLarge,
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::Empty => write!(f, "no time given"),
+            TimestampError::InvalidNumber(part) => write!(f, "\"{}\" is not a number", part),
+            TimestampError::TooManyParts => write!(f, "too many parts, use at most hh:mm:ss"),
+            TimestampError::OutOfRange(part) => {
+                write!(
+                    f,
+                    "\"{}\" has to be below 60 when it isn't the first part",
+                    part
+                )
+            }
+            TimestampError::TooLarge => write!(f, "that is way longer than any song"),
+        }
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+/// Parse a seek position like `1:23`, `1:02:03`, `90`, `1m30s`, `+30` or `-10`
+pub fn parse_seek(input: &str) -> Result<SeekTarget, TimestampError> {
+    let input = input.trim();
+    if let Some(rest) = input.strip_prefix('+') {
+        parse_timestamp(rest).map(SeekTarget::Forward)
+    } else if let Some(rest) = input.strip_prefix('-') {
+        parse_timestamp(rest).map(SeekTarget::Backward)
+    } else {
+        parse_timestamp(input).map(SeekTarget::Absolute)
+    }
+}
+
+/// Parse a duration written as `ss`, `mm:ss`, `hh:mm:ss` or with units like `1h2m3s`
+pub fn parse_timestamp(input: &str) -> Result<Duration, TimestampError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(TimestampError::Empty);
+    }
+    if input.ends_with(['h', 'm', 's']) {
+        return parse_with_units(input);
+    }
+
+    let parts = input.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return Err(TimestampError::TooManyParts);
+    }
+    let mut seconds: u64 = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let value = parse_number(part)?;
+        if i > 0 && value >= 60 {
+            return Err(TimestampError::OutOfRange(part.to_string()));
+        }
+        seconds = seconds
+            .checked_mul(60)
+            .and_then(|seconds| seconds.checked_add(value))
+            .ok_or(TimestampError::TooLarge)?;
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+fn parse_with_units(input: &str) -> Result<Duration, TimestampError> {
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in input.chars() {
+        let multiplier = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => {
+                number.push(c);
+                continue;
+            }
+        };
+        seconds = parse_number(&number)?
+            .checked_mul(multiplier)
+            .and_then(|value| seconds.checked_add(value))
+            .ok_or(TimestampError::TooLarge)?;
+        number.clear();
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+fn parse_number(part: &str) -> Result<u64, TimestampError> {
+    if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+        return Err(TimestampError::InvalidNumber(part.to_owned()));
+    }
+    part.parse()
+        .map_err(|_| TimestampError::InvalidNumber(part.to_owned()))
+}
+
+/// Format a duration as `m:ss`, or `h:mm:ss` when it is an hour or longer
+pub fn format_duration(duration: Duration) -> String {
+    let total = duration.as_secs();
+    let (hours, minutes, seconds) = (total / 3600, (total / 60) % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Draw a text progress bar of the given width showing how far into a song we are
+pub fn progress_bar(elapsed: Duration, total: Duration, width: usize) -> String {
+    let ratio = if total.is_zero() {
+        0.0
+    } else {
+        (elapsed.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0)
+    };
+    let marker = ((ratio * width as f64) as usize).min(width.saturating_sub(1));
+    (0..width)
+        .map(|i| if i == marker { '🔘' } else { '▬' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("90"), Ok(secs(90)));
+        assert_eq!(parse_timestamp("1:23"), Ok(secs(83)));
+        assert_eq!(parse_timestamp("01:02:03"), Ok(secs(3723)));
+        assert_eq!(parse_timestamp("1m30s"), Ok(secs(90)));
+        assert_eq!(parse_timestamp("1h"), Ok(secs(3600)));
+        assert_eq!(parse_timestamp(""), Err(TimestampError::Empty));
+        assert_eq!(
+            parse_timestamp("1:2:3:4"),
+            Err(TimestampError::TooManyParts)
+        );
+        assert_eq!(
+            parse_timestamp("1:75"),
+            Err(TimestampError::OutOfRange("75".to_owned()))
+        );
+        assert_eq!(
+            parse_timestamp("1:a"),
+            Err(TimestampError::InvalidNumber("a".to_owned()))
+        );
+        assert_eq!(
+            parse_timestamp("1::2"),
+            Err(TimestampError::InvalidNumber("".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_seek() {
+        assert_eq!(parse_seek("1:23"), Ok(SeekTarget::Absolute(secs(83))));
+        assert_eq!(parse_seek("+30"), Ok(SeekTarget::Forward(secs(30))));
+        assert_eq!(parse_seek("-10"), Ok(SeekTarget::Backward(secs(10))));
+        assert_eq!(parse_seek("-"), Err(TimestampError::Empty));
+
+        assert_eq!(
+            SeekTarget::Forward(secs(30)).resolve(secs(20)),
+            Ok(secs(50))
+        );
+        assert_eq!(
+            SeekTarget::Backward(secs(30)).resolve(secs(20)),
+            Ok(secs(0))
+        );
+        assert_eq!(SeekTarget::Absolute(secs(5)).resolve(secs(20)), Ok(secs(5)));
+    }
+
+    #[test]
+    fn test_huge_values() {
+        let max = u64::MAX.to_string();
+        assert_eq!(
+            parse_seek(&format!("+{}", max)).and_then(|target| target.resolve(secs(20))),
+            Err(TimestampError::TooLarge)
+        );
+        assert_eq!(
+            parse_timestamp(&format!("{}:00", max)),
+            Err(TimestampError::TooLarge)
+        );
+        assert_eq!(
+            parse_timestamp(&format!("{}h", max)),
+            Err(TimestampError::TooLarge)
+        );
+        assert_eq!(
+            parse_timestamp(&format!("{}s1s", max)),
+            Err(TimestampError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(secs(5)), "0:05");
+        assert_eq!(format_duration(secs(83)), "1:23");
+        assert_eq!(format_duration(secs(3723)), "1:02:03");
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(secs(0), secs(100), 5), "🔘▬▬▬▬");
+        assert_eq!(progress_bar(secs(50), secs(100), 5), "▬▬🔘▬▬");
+        assert_eq!(progress_bar(secs(100), secs(100), 5), "▬▬▬▬🔘");
+        assert_eq!(progress_bar(secs(200), secs(100), 5), "▬▬▬▬🔘");
+        assert_eq!(progress_bar(secs(10), secs(0), 5), "🔘▬▬▬▬");
+    }
+}
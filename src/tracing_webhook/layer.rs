@@ -71,12 +71,11 @@ where
     ) {
         let span = ctx.span(id).unwrap();
 
-        let mut data = vec![];
-        data.push((
+        let data = vec![(
             "Span".to_owned(),
             attrs.metadata().target().to_owned() + "::" + attrs.metadata().name(),
             false,
-        ));
+        )];
         let mut visitor = visitor::EmbedFieldVisitor {
             fields: data,
             field_name_prefix: Some("Span:".to_owned()),
@@ -110,15 +109,10 @@ where
                 .map(|scope| {
                     scope
                         .into_iter()
-                        .flat_map(|s| {
-                            s.extensions()
-                                .get::<Fields>()
-                                .map(|fields| fields.clone())
-                                .unwrap_or_else(|| vec![])
-                        })
+                        .flat_map(|s| s.extensions().get::<Fields>().cloned().unwrap_or_default())
                         .collect::<Vec<_>>()
                 })
-                .unwrap_or_else(|| vec![])
+                .unwrap_or_default()
         } else {
             vec![]
         };
@@ -134,13 +128,7 @@ where
             .field("File", file, true)
             .field("Line", line, true)
             .field("Target", event.metadata().target(), true)
-            .fields(
-                visitor
-                    .fields
-                    .into_iter()
-                    .chain(span_fields.into_iter())
-                    .take(22),
-            );
+            .fields(visitor.fields.into_iter().chain(span_fields).take(22));
         if let Err(err) = self.channel.try_send(Box::new(embed.into())) {
             tracing::error!(err = %err, "failed to send discord payload to given channel");
         }
@@ -11,6 +11,18 @@ pub fn truncate_string_to_char_boundary(s: &mut String, max_len: usize) {
     s.truncate(idx);
 }
 
+/// Put a backslash before every character that would otherwise format the text or end a link
+pub fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '~' | '|' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 pub struct TrimmedEmbed {
     embed: Embed,
     size: usize,
@@ -78,6 +90,24 @@ impl TrimmedEmbed {
         self.fields([(name, value, inline)])
     }
 
+    pub fn footer(mut self, s: impl Into<String>) -> Self {
+        let mut s = s.into();
+        truncate_string_to_char_boundary(&mut s, 2048);
+        let new_size = self.size + s.len();
+        if new_size <= self.max_length() {
+            self.size = new_size;
+            self.embed.footer = Some(create_embed_footer(&s));
+        } else {
+            self.overflowed = true;
+        }
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.embed.url = Some(url.into());
+        self
+    }
+
     pub fn timestamp(mut self, timestamp: Timestamp) -> Self {
         self.embed.timestamp = Some(timestamp);
         self
@@ -98,28 +128,28 @@ fn create_embed_footer(text: &str) -> EmbedFooter {
     toml::from_str::<EmbedFooter>(&toml_str).unwrap()
 }
 
-impl Into<Embed> for TrimmedEmbed {
-    fn into(mut self) -> Embed {
-        if !self.overflowed {
-            return self.embed;
+impl From<TrimmedEmbed> for Embed {
+    fn from(mut trimmed: TrimmedEmbed) -> Embed {
+        if !trimmed.overflowed {
+            return trimmed.embed;
         };
-        let Some(too_big_msg) = self.too_big_msg else {
-            return self.embed;
+        let Some(too_big_msg) = trimmed.too_big_msg else {
+            return trimmed.embed;
         };
-        if let Some(footer) = &mut self.embed.footer {
+        if let Some(footer) = &mut trimmed.embed.footer {
             footer.text += &too_big_msg;
         } else {
             let footer = create_embed_footer(&too_big_msg);
-            self.embed.footer = Some(footer);
+            trimmed.embed.footer = Some(footer);
         }
 
-        self.embed
+        trimmed.embed
     }
 }
 
-impl Into<CreateEmbed> for TrimmedEmbed {
-    fn into(self) -> CreateEmbed {
-        let embed: Embed = self.into();
+impl From<TrimmedEmbed> for CreateEmbed {
+    fn from(trimmed: TrimmedEmbed) -> CreateEmbed {
+        let embed: Embed = trimmed.into();
         embed.into()
     }
 }
@@ -158,4 +188,11 @@ mod tests {
         truncate_string_to_char_boundary(&mut a, 6);
         assert_eq!(a, "🪾f".to_owned());
     }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(escape_markdown("Song"), "Song");
+        assert_eq!(escape_markdown("[Live] *a_b*"), "\\[Live\\] \\*a\\_b\\*");
+        assert_eq!(escape_markdown("a\\b"), "a\\\\b");
+    }
 }
@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use reqwest::Client as HttpClient;
-use serenity::prelude::TypeMapKey;
+use serenity::{all::UserId, prelude::TypeMapKey};
+
+use crate::{player::Prefetch, resolver::StreamTitle};
 
 pub struct HttpKey;
 
@@ -18,3 +22,27 @@ pub struct SongUrlKey;
 impl TypeMapKey for SongUrlKey {
     type Value = String;
 }
+
+pub struct SongDurationKey;
+
+impl TypeMapKey for SongDurationKey {
+    type Value = Duration;
+}
+
+pub struct SongRequesterKey;
+
+impl TypeMapKey for SongRequesterKey {
+    type Value = UserId;
+}
+
+pub struct SongLiveKey;
+
+impl TypeMapKey for SongLiveKey {
+    type Value = StreamTitle;
+}
+
+pub struct PrefetchKey;
+
+impl TypeMapKey for PrefetchKey {
+    type Value = Prefetch;
+}